@@ -0,0 +1,52 @@
+//! Counts the heap allocations of the minimax search per position it visits.
+//! Run it with `cargo run --release --example allocations`.
+
+extern crate tic_tac_toe;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tic_tac_toe::game::grid::Grid;
+use tic_tac_toe::game::rules::{Rules, LineRules, WildRules};
+use tic_tac_toe::game::PlayerId;
+use tic_tac_toe::player::ki::KiPlayer;
+
+// An allocator that counts how often it is asked for memory.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn main() {
+    let mut grid = Grid::new(4, 4, 3);
+    grid.set_cell(1, 1, PlayerId(1));
+    grid.set_cell(2, 2, PlayerId(2));
+    // the classic rules are searched on bitboards, the others on grids
+    count("classic 3x3", &LineRules::default(), &Grid::new(3, 3, 3));
+    count("classic 3x4", &LineRules::default(), &Grid::new(3, 4, 3));
+    count("classic 4x4", &LineRules::default(), &grid);
+    count("misere 3x3", &LineRules { misere: true }, &Grid::new(3, 3, 3));
+    count("wild 3x3", &WildRules::default(), &Grid::new(3, 3, 3));
+}
+
+fn count<R: Rules>(name: &str, rules: &R, grid: &Grid<R::Cell>) {
+    let stones = grid.cell_count() - grid.empty_cells().count();
+    let ki = KiPlayer::new(stones as u32 % 2 + 1);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    ki.best_move(rules, grid);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!("{:<12} {:>9} positions, {:>9} allocations, {:.4} per position", name, ki.nodes(),
+             allocations, allocations as f64 / ki.nodes() as f64);
+}
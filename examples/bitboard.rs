@@ -0,0 +1,114 @@
+//! Compares the bitboard with the grid of cells the classic rules are played on.
+//! Run it with `cargo run --release --example bitboard`.
+
+extern crate tic_tac_toe;
+
+use std::time::{Duration, Instant};
+use tic_tac_toe::game::bitboard::BitBoard;
+use tic_tac_toe::game::grid::Grid;
+use tic_tac_toe::game::grid_observer;
+use tic_tac_toe::game::{CellState, PlayerId, Topology};
+use tic_tac_toe::random::Random;
+
+const POSITIONS: usize = 1000;
+const CHECKS: usize = 200;
+
+fn main() {
+    for &(dimensions, to_win) in &[(&[3, 3][..], 3), (&[4, 4][..], 4), (&[4, 4, 4][..], 4)] {
+        let size: Vec<_> = dimensions.iter().map(|size| size.to_string()).collect();
+        println!("{} board, {} to win:", size.join("x"), to_win);
+        let positions = random_positions(dimensions, to_win, &mut Random::new(1));
+        let boards: Vec<_> = positions.iter()
+            .map(|grid| BitBoard::from_grid(grid, |cell| match *cell {
+                CellState::Set(player) => Some(player),
+                CellState::Unset => None,
+            }).expect("the board fits"))
+            .collect();
+
+        let grid_time = time(|| positions.iter().filter(|grid| grid_observer::check_winner(grid).is_over()).count());
+        let board_time = time(|| boards.iter().filter(|board| board.check_winner().is_over()).count());
+        report("win check", grid_time, board_time);
+
+        let grid_time = time(|| positions.iter().map(|grid| {
+            let mut grid = grid.clone();
+            let empty = grid.empty_cells().next();
+            empty.is_some_and(|index| grid.set_cell_by_index(index, PlayerId(1)))
+        }).filter(|&placed| placed).count());
+        let board_time = time(|| boards.iter().map(|board| {
+            let mut board = board.clone();
+            let empty = board.empty_cells().next();
+            empty.is_some_and(|index| board.set_cell_by_index(index, PlayerId(1)))
+        }).filter(|&placed| placed).count());
+        report("clone and move", grid_time, board_time);
+    }
+
+    println!("Every game on a 3x3 board:");
+    let start = Instant::now();
+    let games = grid_games(&Grid::new(3, 3, 3), PlayerId(1));
+    let grid_time = start.elapsed();
+    let start = Instant::now();
+    let board_games = board_games(&BitBoard::create(&[3, 3], 3, Topology::Bounded).unwrap(), PlayerId(1));
+    let board_time = start.elapsed();
+    assert_eq!(games, board_games);
+    println!("  {} games, grid {:?}, bitboard {:?}, {:.1}x faster", games, grid_time, board_time,
+             speedup(grid_time, board_time));
+}
+
+// Positions from random games, from the first move to the end.
+fn random_positions(dimensions: &[usize], to_win: u32, random: &mut Random) -> Vec<Grid> {
+    let mut positions = Vec::new();
+    while positions.len() < POSITIONS {
+        let mut grid = Grid::with_dimensions(dimensions, to_win);
+        let mut player = PlayerId(1);
+        while !grid_observer::check_winner(&grid).is_over() && positions.len() < POSITIONS {
+            let empty = grid.get_indices_with_state(CellState::Unset);
+            grid.set_cell_by_index(empty[random.below(empty.len())], player);
+            positions.push(grid.clone());
+            player = PlayerId(player.0 % 2 + 1);
+        }
+    }
+    positions
+}
+
+fn grid_games(grid: &Grid, player: PlayerId) -> u64 {
+    if grid_observer::check_winner(grid).is_over() {
+        return 1;
+    }
+    grid.empty_cells().map(|index| {
+        let mut grid = grid.clone();
+        grid.set_cell_by_index(index, player);
+        grid_games(&grid, PlayerId(player.0 % 2 + 1))
+    }).sum()
+}
+
+fn board_games(board: &BitBoard, player: PlayerId) -> u64 {
+    if board.check_winner().is_over() {
+        return 1;
+    }
+    board.empty_cells().map(|index| {
+        let mut board = board.clone();
+        board.set_cell_by_index(index, player);
+        board_games(&board, PlayerId(player.0 % 2 + 1))
+    }).sum()
+}
+
+// The time per position of running `check` over all of them, `CHECKS` times.
+fn time<F: Fn() -> usize>(check: F) -> Duration {
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0 .. CHECKS {
+        total += check();
+    }
+    // keeps the checks from being optimized away
+    assert!(total <= CHECKS * POSITIONS);
+    start.elapsed() / (CHECKS * POSITIONS) as u32
+}
+
+fn report(name: &str, grid_time: Duration, board_time: Duration) {
+    println!("  {:<15} grid {:>10?}, bitboard {:>10?}, {:.1}x faster", name, grid_time, board_time,
+             speedup(grid_time, board_time));
+}
+
+fn speedup(slow: Duration, fast: Duration) -> f64 {
+    slow.as_secs_f64() / fast.as_secs_f64().max(1e-12)
+}
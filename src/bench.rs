@@ -0,0 +1,265 @@
+//! A small benchmark harness in the spirit of criterion, without its dependencies: every benchmark
+//! is warmed up, then timed in several samples, and the median time per iteration is compared with
+//! a saved baseline to spot regressions.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+use ::game::bitboard::BitBoard;
+use ::game::grid::Grid;
+use ::game::grid_observer;
+use ::game::rules::{Rules, LineRules, WildRules};
+use ::game::{CellState, PlayerId, Topology};
+use ::player::ki::KiPlayer;
+use ::player::Player;
+use ::player::mcts::{MctsPlayer, Budget};
+use ::random::Random;
+
+/// How much slower than its baseline a benchmark may get before it counts as a regression.
+pub const REGRESSION_THRESHOLD: f64 = 0.1;
+
+/// How long a benchmark runs.
+pub struct Settings {
+    pub warm_up: Duration,
+    // the time all samples together should take, slow benchmarks take at least one iteration each
+    pub measurement: Duration,
+    pub samples: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            warm_up: Duration::from_millis(200),
+            measurement: Duration::from_secs(1),
+            samples: 20,
+        }
+    }
+}
+
+/// The time one iteration of a benchmark takes.
+#[derive(Debug)]
+pub struct Measurement {
+    pub name: String,
+    pub median: Duration,
+    // the fastest and the slowest sample
+    pub min: Duration,
+    pub max: Duration,
+}
+
+/// Times `routine`, which should return what it computed so that isn't optimized away.
+pub fn measure<T, F: FnMut() -> T>(name: &str, settings: &Settings, mut routine: F) -> Measurement {
+    let start = Instant::now();
+    let mut iterations = 0u64;
+    while iterations == 0 || start.elapsed() < settings.warm_up {
+        black_box(routine());
+        iterations += 1;
+    }
+    let estimate = start.elapsed() / iterations as u32;
+    let sample_time = settings.measurement / settings.samples;
+    let per_sample = (sample_time.as_nanos() / estimate.as_nanos().max(1)).max(1) as u32;
+
+    let mut times: Vec<Duration> = (0 .. settings.samples).map(|_| {
+        let start = Instant::now();
+        for _ in 0 .. per_sample {
+            black_box(routine());
+        }
+        start.elapsed() / per_sample
+    }).collect();
+    times.sort();
+    Measurement {
+        name: name.to_string(),
+        median: times[times.len() / 2],
+        min: times[0],
+        max: times[times.len() - 1],
+    }
+}
+
+/// The median times of an earlier run, by benchmark name.
+///
+/// In a file every benchmark is a line with its name, a tab and the nanoseconds per iteration.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Baseline {
+    times: BTreeMap<String, Duration>,
+}
+
+impl Baseline {
+    pub fn new(measurements: &[Measurement]) -> Baseline {
+        Baseline {
+            times: measurements.iter().map(|measurement| (measurement.name.clone(), measurement.median)).collect(),
+        }
+    }
+
+    pub fn load(path: &str) -> io::Result<Baseline> {
+        Baseline::read(&fs::read_to_string(path)?).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.write())
+    }
+
+    pub fn read(text: &str) -> Result<Baseline, String> {
+        let mut times = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut parts = line.splitn(2, '\t');
+            let name = parts.next().unwrap_or("");
+            let nanos = parts.next().and_then(|nanos| nanos.trim().parse().ok())
+                .ok_or_else(|| format!("invalid baseline line '{}'", line))?;
+            times.insert(name.to_string(), Duration::from_nanos(nanos));
+        }
+        Ok(Baseline {
+            times,
+        })
+    }
+
+    pub fn write(&self) -> String {
+        self.times.iter().map(|(name, time)| format!("{}\t{}\n", name, time.as_nanos())).collect()
+    }
+
+    /// How much slower the benchmark got, e.g. 0.25 for 25% slower or -0.5 for twice as fast.
+    /// None if the baseline doesn't know the benchmark.
+    pub fn change(&self, measurement: &Measurement) -> Option<f64> {
+        self.times.get(&measurement.name).map(|&time| {
+            measurement.median.as_secs_f64() / time.as_secs_f64().max(1e-12) - 1.0
+        })
+    }
+}
+
+/// Runs every benchmark whose name contains `filter`, handing each measurement to `report`
+/// as soon as it is done.
+pub fn run_suite<F: FnMut(&Measurement)>(settings: &Settings, filter: &str, mut report: F) -> Vec<Measurement> {
+    let mut measurements = Vec::new();
+    {
+        let mut bench = |name: &str, routine: &mut dyn FnMut() -> u64| {
+            if name.contains(filter) {
+                let measurement = measure(name, settings, routine);
+                report(&measurement);
+                measurements.push(measurement);
+            }
+        };
+
+        for &(size, dimensions, to_win) in &[("3x3", &[3, 3][..], 3), ("7x7", &[7, 7][..], 5),
+                                             ("4x4x4", &[4, 4, 4][..], 4)] {
+            for &(fill, share) in &[("empty", 0.0), ("half", 0.5), ("full", 1.0)] {
+                let grid = filled_grid(dimensions, to_win, share);
+                bench(&format!("check_winner {} {}", size, fill),
+                      &mut || grid_observer::check_winner(&grid).is_over() as u64);
+                if let Some(board) = BitBoard::from_grid(&grid, |cell| LineRules::default().owner(cell)) {
+                    bench(&format!("bitboard check_winner {} {}", size, fill),
+                          &mut || board.check_winner().is_over() as u64);
+                }
+            }
+            let grid = filled_grid(dimensions, to_win, 0.5);
+            bench(&format!("grid clone {}", size), &mut || grid.clone().cell_count() as u64);
+            // rows and columns only exist in two dimensions
+            if dimensions.len() == 2 {
+                bench(&format!("get_cells_with_state {}", size),
+                      &mut || grid.get_cells_with_state(CellState::Unset).len() as u64);
+            }
+            bench(&format!("empty_cells {}", size), &mut || grid.empty_cells().count() as u64);
+        }
+
+        let mut grid = Grid::new(4, 4, 3);
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(2, 2, PlayerId(2));
+        let mut wild = Grid::new(3, 3, 3);
+        wild.set_cell(1, 1, PlayerId(1));
+        bench("ai minimax classic 3x3", &mut || best_move(&LineRules::default(), &Grid::new(3, 3, 3), 1));
+        bench("ai minimax classic 3x4", &mut || best_move(&LineRules::default(), &Grid::new(3, 4, 3), 1));
+        bench("ai minimax classic 4x4", &mut || best_move(&LineRules::default(), &grid, 1));
+        bench("ai minimax misere 3x3", &mut || best_move(&LineRules { misere: true }, &Grid::new(3, 3, 3), 1));
+        bench("ai minimax wild 3x3", &mut || best_move(&WildRules::default(), &wild, 2));
+        bench("ai mcts classic 3x3", &mut || {
+            let mut grid = Grid::new(3, 3, 3);
+            MctsPlayer::new(1, Budget::Iterations(1000)).make_turn(&LineRules::default(), &mut grid);
+            grid.empty_cells().count() as u64
+        });
+    }
+    measurements
+}
+
+fn best_move<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: u32) -> u64 {
+    let ki = KiPlayer::new(player);
+    black_box(ki.best_move(rules, grid));
+    ki.nodes()
+}
+
+// A grid with `share` of its cells taken by both players in turn, the same every time.
+fn filled_grid(dimensions: &[usize], to_win: u32, share: f64) -> Grid {
+    let mut grid = Grid::with_topology(dimensions, to_win, Topology::Bounded);
+    let mut random = Random::new(42);
+    let stones = (grid.cell_count() as f64 * share) as usize;
+    for stone in 0 .. stones {
+        let empty = grid.get_indices_with_state(CellState::Unset);
+        grid.set_cell_by_index(empty[random.below(empty.len())], PlayerId(stone as u32 % 2 + 1));
+    }
+    grid
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn quick() -> Settings {
+        Settings {
+            warm_up: Duration::from_millis(1),
+            measurement: Duration::from_millis(5),
+            samples: 5,
+        }
+    }
+
+    #[test]
+    fn test_measure() {
+        let mut calls = 0;
+        let measurement = measure("count", &quick(), || { calls += 1; calls });
+        assert_eq!("count", measurement.name);
+        assert!(measurement.min <= measurement.median && measurement.median <= measurement.max);
+        assert!(calls >= 6, "the warm up and every sample run at least once");
+    }
+
+    #[test]
+    fn test_baseline_round_trip_and_change() {
+        let measurements = vec![
+            Measurement {
+                name: "fast".to_string(),
+                median: Duration::from_nanos(100),
+                min: Duration::from_nanos(90),
+                max: Duration::from_nanos(120),
+            },
+            Measurement {
+                name: "ai minimax classic 3x3".to_string(),
+                median: Duration::from_micros(400),
+                min: Duration::from_micros(390),
+                max: Duration::from_micros(420),
+            },
+        ];
+        let baseline = Baseline::new(&measurements);
+        assert_eq!("ai minimax classic 3x3\t400000\nfast\t100\n", baseline.write());
+        assert_eq!(baseline, Baseline::read(&baseline.write()).unwrap());
+
+        let slower = Measurement {
+            name: "fast".to_string(),
+            median: Duration::from_nanos(150),
+            min: Duration::from_nanos(150),
+            max: Duration::from_nanos(150),
+        };
+        assert!((baseline.change(&slower).unwrap() - 0.5).abs() < 1e-9);
+        let unknown = Measurement {
+            name: "new".to_string(),
+            ..slower
+        };
+        assert_eq!(None, baseline.change(&unknown));
+        assert!(Baseline::read("fast 100").is_err());
+    }
+
+    #[test]
+    fn test_filter_selects_benchmarks() {
+        let measurements = run_suite(&quick(), "grid clone 3x3", |_| {});
+        assert_eq!(1, measurements.len());
+        assert_eq!("grid clone 3x3", measurements[0].name);
+    }
+}
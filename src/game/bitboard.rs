@@ -0,0 +1,277 @@
+use std::rc::Rc;
+use super::grid::Grid;
+use super::{Cell, CellState, PlayerId, GameState, Topology, PLAYER_COUNT};
+use super::grid_observer;
+use super::symmetry;
+
+/// The most cells a bitboard holds, enough for Qubic.
+pub const MAX_CELLS: usize = 64;
+
+/// A grid of the classic rules with one bitset of cells per player, so placing a stone and
+/// checking for a winner are a few bit operations and cloning doesn't allocate.
+/// Offers the same accessors as `Grid`, bit `index` being the cell `index` of the grid.
+pub struct BitBoard {
+    // the cells of player `id` are in `stones[id - 1]`
+    stones: [u64; PLAYER_COUNT as usize],
+    cell_count: usize,
+    // every run of `to_win` cells that wins the game, as a mask of its cells
+    lines: Rc<[u64]>,
+    // every rotation and reflection, as the index each cell is moved to
+    symmetries: Rc<[Box<[usize]>]>,
+}
+
+impl Clone for BitBoard {
+    fn clone(&self) -> Self {
+        BitBoard {
+            stones: self.stones,
+            cell_count: self.cell_count,
+            lines: self.lines.clone(),
+            symmetries: self.symmetries.clone(),
+        }
+    }
+}
+
+impl BitBoard {
+    /// Creates an empty board, or none if it has more than `MAX_CELLS` cells.
+    pub fn create(dimensions: &[usize], streak_to_win: u32, topology: Topology) -> Option<BitBoard> {
+        let cell_count: usize = dimensions.iter().product();
+        if cell_count > MAX_CELLS {
+            return None;
+        }
+        let lines = grid_observer::lines(dimensions, streak_to_win, topology);
+        Some(BitBoard {
+            stones: [0; PLAYER_COUNT as usize],
+            cell_count,
+            lines: lines.iter().map(|line| mask_of(line)).collect::<Vec<_>>().into(),
+            symmetries: symmetry::symmetries(dimensions).into(),
+        })
+    }
+
+    /// The board of `grid`, with `owner` telling whose stone a cell is. Gives none if the grid
+    /// is too large or has a set cell without an owner.
+    pub fn from_grid<C: Cell, F: Fn(&C) -> Option<PlayerId>>(grid: &Grid<C>, owner: F) -> Option<BitBoard> {
+        if grid.cell_count() > MAX_CELLS {
+            return None;
+        }
+        let mut board = BitBoard {
+            stones: [0; PLAYER_COUNT as usize],
+            cell_count: grid.cell_count(),
+            lines: grid.lines().iter().map(|line| mask_of(line)).collect::<Vec<_>>().into(),
+            symmetries: grid.symmetries().to_vec().into(),
+        };
+        for index in 0 .. grid.cell_count() {
+            let cell = grid.get_cell_by_index(index);
+            if *cell != C::unset() {
+                board.set_cell_by_index(index, owner(cell)?);
+            }
+        }
+        Some(board)
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    pub fn get_cell_by_index(&self, index: usize) -> CellState {
+        let bit = 1 << index;
+        match self.stones.iter().position(|&stones| stones & bit != 0) {
+            Some(player) => CellState::Set(PlayerId(player as u32 + 1)),
+            None => CellState::Unset,
+        }
+    }
+
+    /// Places a stone of `player` on the unset cell at `index`.
+    pub fn set_cell_by_index(&mut self, index: usize, player: PlayerId) -> bool {
+        let bit = 1 << index;
+        if self.occupied() & bit != 0 {
+            return false;
+        }
+        let PlayerId(id) = player;
+        self.stones[id as usize - 1] |= bit;
+        true
+    }
+
+    /// The cells of `player` as a bitset.
+    pub fn stones(&self, player: PlayerId) -> u64 {
+        let PlayerId(id) = player;
+        self.stones[id as usize - 1]
+    }
+
+    /// The set cells as a bitset.
+    pub fn occupied(&self) -> u64 {
+        self.stones.iter().fold(0, |occupied, &stones| occupied | stones)
+    }
+
+    /// Every run of cells that wins the game, as a mask of its cells.
+    pub fn lines(&self) -> &[u64] {
+        &self.lines
+    }
+
+    /// The unset cells as a bitset.
+    pub fn empty_mask(&self) -> u64 {
+        !self.occupied() & self.all_cells()
+    }
+
+    /// The indices of the unset cells, without collecting them.
+    pub fn empty_cells(&self) -> Indices {
+        Indices(self.empty_mask())
+    }
+
+    pub fn get_indices_with_state(&self, state: CellState) -> Vec<usize> {
+        let cells = match state {
+            CellState::Unset => self.empty_mask(),
+            CellState::Set(player) => self.stones(player),
+        };
+        Indices(cells).collect()
+    }
+
+    /// The same as `grid_observer::check_winner` on the grid: a full line of one player wins.
+    pub fn check_winner(&self) -> GameState {
+        for (index, &stones) in self.stones.iter().enumerate() {
+            if self.lines.iter().any(|&line| line & !stones == 0) {
+                return GameState::Win(PlayerId(index as u32 + 1));
+            }
+        }
+        if self.empty_mask() == 0 {
+            GameState::Draw
+        } else {
+            GameState::Mid
+        }
+    }
+
+    /// The smallest of all rotations and reflections of the board, which is the same
+    /// for every board that only differs by a symmetry.
+    pub fn canonical(&self) -> BitBoard {
+        let mut canonical = self.clone();
+        for symmetry in self.symmetries.iter().skip(1) {
+            let mut stones = [0; PLAYER_COUNT as usize];
+            for (transformed, &original) in stones.iter_mut().zip(self.stones.iter()) {
+                for index in Indices(original) {
+                    *transformed |= 1 << symmetry[index];
+                }
+            }
+            if stones < canonical.stones {
+                canonical.stones = stones;
+            }
+        }
+        canonical
+    }
+
+    /// The bitsets of all players, the same for equal positions on boards of the same kind.
+    pub fn key(&self) -> [u64; PLAYER_COUNT as usize] {
+        self.stones
+    }
+
+    fn all_cells(&self) -> u64 {
+        if self.cell_count == MAX_CELLS { !0 } else { (1 << self.cell_count) - 1 }
+    }
+}
+
+/// The indices of the set bits of a bitset, lowest first.
+pub struct Indices(pub u64);
+
+impl Iterator for Indices {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(index)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+
+fn mask_of(line: &[usize]) -> u64 {
+    line.iter().fold(0, |mask, &index| mask | 1 << index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::grid_observer;
+    use ::game::{CellState, PlayerId, Topology};
+    use ::random::Random;
+
+    fn owner(cell: &CellState) -> Option<PlayerId> {
+        match *cell {
+            CellState::Set(player) => Some(player),
+            CellState::Unset => None,
+        }
+    }
+
+    fn same_state(first: GameState, second: GameState) -> bool {
+        match (first, second) {
+            (GameState::Win(first), GameState::Win(second)) => first == second,
+            (GameState::Draw, GameState::Draw) | (GameState::Mid, GameState::Mid) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_grid() {
+        let mut random = Random::new(5);
+        for &(ref dimensions, to_win, topology) in &[(vec![3, 3], 3, Topology::Bounded),
+                                                     (vec![4, 5], 3, Topology::Toroidal),
+                                                     (vec![4, 4, 4], 4, Topology::Bounded)] {
+            for _ in 0 .. 50 {
+                let mut grid = Grid::with_topology(dimensions, to_win, topology);
+                let mut board = BitBoard::create(dimensions, to_win, topology).unwrap();
+                let mut player = PlayerId(1);
+                while let GameState::Mid = grid_observer::check_winner(&grid) {
+                    let empty = grid.get_indices_with_state(CellState::Unset);
+                    assert_eq!(empty, board.get_indices_with_state(CellState::Unset));
+                    let index = empty[random.below(empty.len())];
+                    assert!(grid.set_cell_by_index(index, player));
+                    assert!(board.set_cell_by_index(index, player));
+                    assert!(!board.set_cell_by_index(index, player));
+                    assert!(same_state(grid_observer::check_winner(&grid), board.check_winner()));
+                    player = PlayerId(player.0 % 2 + 1);
+                }
+                let converted = BitBoard::from_grid(&grid, owner).unwrap();
+                assert_eq!(board.key(), converted.key());
+                for index in 0 .. grid.cell_count() {
+                    assert_eq!(*grid.get_cell_by_index(index), board.get_cell_by_index(index));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical_matches_symmetric_boards() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 1, PlayerId(1));
+        grid.set_cell(2, 2, PlayerId(2));
+        let board = BitBoard::from_grid(&grid, owner).unwrap();
+        let canonical = board.canonical().key();
+        for symmetry in grid.symmetries() {
+            let transformed = BitBoard::from_grid(&grid.transformed(symmetry), owner).unwrap();
+            assert_eq!(canonical, transformed.canonical().key());
+        }
+    }
+
+    #[test]
+    fn test_too_large_boards() {
+        assert!(BitBoard::create(&[4, 4, 4], 4, Topology::Bounded).is_some());
+        assert!(BitBoard::create(&[5, 5, 5], 4, Topology::Bounded).is_none());
+        assert!(BitBoard::from_grid(&Grid::new(9, 9, 5), owner).is_none());
+    }
+
+    #[test]
+    fn test_full_board_is_a_draw() {
+        let mut board = BitBoard::create(&[8, 8], 9, Topology::Bounded).unwrap();
+        for index in 0 .. 64 {
+            board.set_cell_by_index(index, PlayerId(index as u32 % 2 + 1));
+        }
+        assert_eq!(0, board.empty_mask());
+        assert_eq!(None, board.empty_cells().next());
+        assert!(same_state(GameState::Draw, board.check_winner()));
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::time::Duration;
+
+/// A chess-style clock for one player: the time left for the rest of the game, which grows by
+/// the increment after every move.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+}
+
+/// How much time a player may think.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub enum TimeControl {
+    Unlimited,
+    PerMove(Duration),
+    Clock(Clock),
+}
+
+impl Clock {
+    pub fn new(total: Duration, increment: Duration) -> Clock {
+        Clock {
+            remaining: total,
+            increment,
+        }
+    }
+
+    /// How long the next move may take if about `moves_left` moves are still to be made,
+    /// spreading the remaining time evenly and always leaving a little in reserve.
+    pub fn budget(&self, moves_left: u32) -> Duration {
+        let share = self.remaining / moves_left.max(1) + self.increment;
+        share.min(usable(self.remaining))
+    }
+
+    /// Takes the time of a move from the clock. Returns false if the time ran out.
+    pub fn spend(&mut self, elapsed: Duration) -> bool {
+        match self.remaining.checked_sub(elapsed) {
+            Some(remaining) => {
+                self.remaining = remaining + self.increment;
+                true
+            },
+            None => {
+                self.remaining = Duration::from_secs(0);
+                false
+            },
+        }
+    }
+}
+
+/// The part of `time` a search may use, keeping a reserve for finishing the move: a search
+/// only notices its time is up between positions, so it always runs a little over.
+pub fn usable(time: Duration) -> Duration {
+    time * 9 / 10
+}
+
+impl TimeControl {
+    /// How long the next move may take, if there is any limit.
+    pub fn budget(&self, moves_left: u32) -> Option<Duration> {
+        match *self {
+            TimeControl::Unlimited => None,
+            TimeControl::PerMove(limit) => Some(usable(limit)),
+            TimeControl::Clock(ref clock) => Some(clock.budget(moves_left)),
+        }
+    }
+
+    /// Takes the time of a move from the clock, if there is one.
+    /// Returns false if the player ran out of time.
+    pub fn spend(&mut self, elapsed: Duration) -> bool {
+        match *self {
+            TimeControl::Unlimited => true,
+            TimeControl::PerMove(limit) => elapsed <= limit,
+            TimeControl::Clock(ref mut clock) => clock.spend(elapsed),
+        }
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.remaining.as_secs();
+        write!(f, "{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeControl::Unlimited => write!(f, "no time limit"),
+            TimeControl::PerMove(limit) => write!(f, "{:.1}s per move", limit.as_secs_f64()),
+            TimeControl::Clock(ref clock) => write!(f, "{}", clock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_budget_spreads_remaining_time() {
+        let clock = Clock::new(Duration::from_secs(60), Duration::from_secs(1));
+        assert_eq!(Duration::from_secs(7), clock.budget(10));
+        assert_eq!(Duration::from_secs(54), clock.budget(0));
+    }
+
+    #[test]
+    fn test_spend_adds_increment() {
+        let mut clock = Clock::new(Duration::from_secs(10), Duration::from_secs(2));
+        assert!(clock.spend(Duration::from_secs(3)));
+        assert_eq!(Duration::from_secs(9), clock.remaining);
+        assert!(!clock.spend(Duration::from_secs(10)));
+        assert_eq!(Duration::from_secs(0), clock.remaining);
+    }
+
+    #[test]
+    fn test_per_move_limit() {
+        let mut time_control = TimeControl::PerMove(Duration::from_secs(30));
+        assert!(time_control.spend(Duration::from_secs(29)));
+        assert!(!time_control.spend(Duration::from_secs(31)));
+        assert_eq!(Some(Duration::from_secs(27)), time_control.budget(10));
+    }
+
+    #[test]
+    fn test_display() {
+        let clock = Clock::new(Duration::from_millis(125_900), Duration::from_secs(0));
+        assert_eq!("2:05", TimeControl::Clock(clock).to_string());
+        assert_eq!("1.5s per move", TimeControl::PerMove(Duration::from_millis(1500)).to_string());
+    }
+}
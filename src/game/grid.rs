@@ -1,70 +1,228 @@
-use super::{CellState, PlayerId};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use super::{Cell, CellState, PlayerId, Topology};
+use super::grid_observer;
+use super::symmetry;
 
-pub struct Grid {
-    // inner [0, 2] would be the 3rd column of the 1st row
-    inner: Box<[CellState]>,
-    pub column_count: usize,
-    pub row_count: usize,
+pub struct Grid<C: Cell = CellState> {
+    // cells are stored with the last dimension varying fastest, so in a 2D grid
+    // inner[0 * column_count + 2] would be the 3rd column of the 1st row
+    inner: Box<[C]>,
+    dimensions: Rc<[usize]>,
+    // every run of `to_win` cells that wins the game, as indices into `inner`
+    lines: Rc<[Box<[usize]>]>,
+    // every rotation and reflection, as the index each cell is moved to
+    symmetries: Rc<[Box<[usize]>]>,
+    topology: Topology,
     pub to_win: u32,
+    last_mover: Option<PlayerId>,
 }
 
-impl Clone for Grid {
+impl<C: Cell> Clone for Grid<C> {
     fn clone(&self) -> Self {
         Grid {
             inner: self.inner.clone(),
-            column_count: self.column_count,
-            row_count: self.row_count,
+            dimensions: self.dimensions.clone(),
+            lines: self.lines.clone(),
+            symmetries: self.symmetries.clone(),
+            topology: self.topology,
             to_win: self.to_win,
+            last_mover: self.last_mover,
         }
     }
 }
 
+impl<C: Cell> PartialEq for Grid<C> {
+    fn eq(&self, other: &Grid<C>) -> bool {
+        self.dimensions == other.dimensions && self.inner == other.inner
+    }
+}
+
+impl<C: Cell> Eq for Grid<C> {}
+
+impl<C: Cell> Hash for Grid<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.dimensions.hash(state);
+        self.inner.hash(state);
+    }
+}
+
 impl Grid {
     pub fn new(row_count: usize, column_count: usize, streak_to_win: u32) -> Grid {
+        Grid::with_dimensions(&[row_count, column_count], streak_to_win)
+    }
+
+    /// Creates a grid with an arbitrary number of dimensions, e.g. `&[4, 4, 4]` for Qubic.
+    /// The last two dimensions are the rows and columns, any further ones are layers.
+    pub fn with_dimensions(dimensions: &[usize], streak_to_win: u32) -> Grid {
+        Grid::with_topology(dimensions, streak_to_win, Topology::Bounded)
+    }
+
+    pub fn with_topology(dimensions: &[usize], streak_to_win: u32, topology: Topology) -> Grid {
+        Grid::create(dimensions, streak_to_win, topology)
+    }
+
+    pub fn set_cell(&mut self, row: usize, column: usize, player: PlayerId) -> bool {
+        let index = self.calc_index(row, column);
+        self.set_cell_by_index(index, player)
+    }
+
+    pub fn set_cell_at(&mut self, coordinates: &[usize], player: PlayerId) -> bool {
+        let index = self.index_of(coordinates);
+        self.set_cell_by_index(index, player)
+    }
+
+    pub fn set_cell_by_index(&mut self, index: usize, player: PlayerId) -> bool {
+        self.set_symbol_by_index(index, player, player)
+    }
+
+    /// Places the symbol of player `symbol` on behalf of player `mover`, which differ in wild games.
+    pub fn set_symbol_by_index(&mut self, index: usize, symbol: PlayerId, mover: PlayerId) -> bool {
+        self.place(index, CellState::Set(symbol), mover)
+    }
+}
+
+impl<C: Cell> Grid<C> {
+    /// Creates an empty grid of any cell type, e.g. `Grid::<NumberCell>::create(..)`.
+    pub fn create(dimensions: &[usize], streak_to_win: u32, topology: Topology) -> Grid<C> {
+        if dimensions.is_empty() {
+            panic!("a grid needs at least one dimension");
+        }
+        let cell_count = dimensions.iter().product();
         Grid {
-            inner: vec![CellState::Unset; column_count * row_count].into_boxed_slice(),
-            column_count: column_count,
-            row_count: row_count,
+            inner: vec![C::unset(); cell_count].into_boxed_slice(),
+            dimensions: dimensions.into(),
+            lines: grid_observer::lines(dimensions, streak_to_win, topology).into(),
+            symmetries: symmetry::symmetries(dimensions).into(),
+            topology,
             to_win: streak_to_win,
+            last_mover: None,
+        }
+    }
+
+    pub fn dimensions(&self) -> &[usize] {
+        &self.dimensions
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn row_count(&self) -> usize {
+        if self.dimensions.len() < 2 {
+            1
+        } else {
+            self.dimensions[self.dimensions.len() - 2]
         }
     }
 
+    pub fn column_count(&self) -> usize {
+        self.dimensions[self.dimensions.len() - 1]
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn lines(&self) -> &[Box<[usize]>] {
+        &self.lines
+    }
+
+    pub fn symmetries(&self) -> &[Box<[usize]>] {
+        &self.symmetries
+    }
+
+    /// The grid with every cell moved to the index `symmetry` maps it to.
+    pub fn transformed(&self, symmetry: &[usize]) -> Grid<C> {
+        let mut grid = self.clone();
+        for (index, &cell) in self.inner.iter().enumerate() {
+            grid.inner[symmetry[index]] = cell;
+        }
+        grid
+    }
+
+    /// The smallest of all rotations and reflections of the grid, which is the same
+    /// for every grid that only differs by a symmetry.
+    pub fn canonical(&self) -> Grid<C> {
+        let mut canonical = self.clone();
+        for symmetry in self.symmetries.iter().skip(1) {
+            let grid = self.transformed(symmetry);
+            if grid.inner < canonical.inner {
+                canonical = grid;
+            }
+        }
+        canonical
+    }
+
+    pub fn index_of(&self, coordinates: &[usize]) -> usize {
+        if coordinates.len() != self.dimensions.len() {
+            panic!("the grid has {} dimensions but {} coordinates were given",
+                   self.dimensions.len(), coordinates.len())
+        }
+        let mut index = 0;
+        for (&coordinate, &size) in coordinates.iter().zip(self.dimensions.iter()) {
+            if coordinate >= size {
+                panic!("index out of bounds: the dimension size is {} but the coordinate accessed is {}",
+                       size, coordinate)
+            }
+            index = index * size + coordinate;
+        }
+        index
+    }
+
+    pub fn coordinates(&self, index: usize) -> Vec<usize> {
+        grid_observer::coordinates_of(&self.dimensions, index)
+    }
+
     fn calc_index(&self, row: usize, column: usize) -> usize {
-        if row >= self.row_count {
+        if self.dimensions.len() != 2 {
+            panic!("the grid has {} dimensions, use the coordinate based accessors",
+                   self.dimensions.len())
+        }
+        if row >= self.row_count() {
             panic!("index out of bounds: the row_count is {} but the row accessed is {}",
-                   self.row_count, row)
+                   self.row_count(), row)
         }
-        if column >= self.column_count {
+        if column >= self.column_count() {
             panic!("index out of bounds: the column_count is {} but the column accessed is {}",
-                   self.column_count, column)
+                   self.column_count(), column)
         }
 
-        column + row * self.column_count
+        column + row * self.column_count()
+    }
+
+    pub fn get_cell(&self, row: usize, column: usize) -> &C {
+        &self.inner[self.calc_index(row, column)]
     }
 
-    fn get_mut_cell(&mut self, row: usize, column: usize) -> &mut CellState {
-        &mut self.inner[self.calc_index(row, column)]
+    pub fn get_cell_at(&self, coordinates: &[usize]) -> &C {
+        &self.inner[self.index_of(coordinates)]
     }
 
-    pub fn get_cell(&self, row: usize, column: usize) -> &CellState {
-        &self.inner[self.calc_index(row, column)]
+    pub fn get_cell_by_index(&self, index: usize) -> &C {
+        &self.inner[index]
     }
 
-    pub fn set_cell(&mut self, row: usize, column: usize, player: PlayerId) -> bool {
-        let cell = self.get_mut_cell(row, column);
-        match cell {
-            &mut CellState::Unset => {
-                *cell = CellState::Set(player);
-                return true;
-            },
-            &mut CellState::Set(_) => return false,
+    /// Places `cell` on the unset cell at `index` on behalf of player `mover`.
+    pub fn place(&mut self, index: usize, cell: C, mover: PlayerId) -> bool {
+        if self.inner[index] == C::unset() {
+            self.inner[index] = cell;
+            self.last_mover = Some(mover);
+            true
+        } else {
+            false
         }
     }
 
-    pub fn get_cells_with_state(&self, state: CellState) -> Vec<(usize, usize)> {
+    /// The player who made the latest move, if any.
+    pub fn last_mover(&self) -> Option<PlayerId> {
+        self.last_mover
+    }
+
+    pub fn get_cells_with_state(&self, state: C) -> Vec<(usize, usize)> {
         let mut result = Vec::new();
-        for row_nr in 0 .. self.row_count {
-            for col_nr in 0 .. self.column_count {
+        for row_nr in 0 .. self.row_count() {
+            for col_nr in 0 .. self.column_count() {
                 if *self.get_cell(row_nr, col_nr) == state {
                     result.push((row_nr, col_nr));
                 }
@@ -73,16 +231,32 @@ impl Grid {
         result
     }
 
+    pub fn get_indices_with_state(&self, state: C) -> Vec<usize> {
+        (0 .. self.inner.len()).filter(|&index| self.inner[index] == state).collect()
+    }
+
+    /// The indices of the unset cells, without collecting them.
+    pub fn empty_cells(&self) -> impl Iterator<Item = usize> + '_ {
+        (0 .. self.inner.len()).filter(move |&index| self.inner[index] == C::unset())
+    }
+
     pub fn pretty_print(&self) {
-        for row_nr in 0 .. self.row_count {
-            for cell_nr in 0 .. self.column_count {
-                let string = match self.get_cell(row_nr, cell_nr) {
-                    &CellState::Unset => format!("_"),
-                    &CellState::Set(PlayerId(id)) => format!("{}", id),
-                };
-                print!("|{}", string);
+        let layer_size = self.row_count() * self.column_count();
+        for layer_start in (0 .. self.inner.len()).step_by(layer_size) {
+            if self.dimensions.len() > 2 {
+                let coordinates = self.coordinates(layer_start);
+                let layer: Vec<_> = coordinates[.. coordinates.len() - 2].iter()
+                    .map(|coordinate| coordinate.to_string())
+                    .collect();
+                println!("Layer {}:", layer.join(" "));
+            }
+            for row_nr in 0 .. self.row_count() {
+                for cell_nr in 0 .. self.column_count() {
+                    let index = layer_start + row_nr * self.column_count() + cell_nr;
+                    print!("|{}", self.inner[index]);
+                }
+                println!("|");
             }
-            println!("|");
         }
     }
 
@@ -97,9 +271,9 @@ mod test {
     #[test]
     fn test_grid() {
         let grid = Grid::new(3, 3, 3);
-        match grid.get_cell(0, 0) {
-            &CellState::Unset => return,
-            &CellState::Set(_) => panic!("Cell in a new grid is set even though it shouldn't."),
+        match *grid.get_cell(0, 0) {
+            CellState::Unset => (),
+            CellState::Set(_) => panic!("Cell in a new grid is set even though it shouldn't."),
         }
     }
 
@@ -107,10 +281,10 @@ mod test {
     fn test_set_cell() {
         let mut grid = Grid::new(3, 3, 3);
         if grid.set_cell(0, 0, PlayerId(1)) {
-            match grid.get_cell(0, 0) {
-                &CellState::Unset => panic!("Cell should be set after calling set_cell"),
-                &CellState::Set(PlayerId(1)) => return,
-                &CellState::Set(_) => panic!("Cell is set by the wrong player"),
+            match *grid.get_cell(0, 0) {
+                CellState::Unset => panic!("Cell should be set after calling set_cell"),
+                CellState::Set(PlayerId(1)) => (),
+                CellState::Set(_) => panic!("Cell is set by the wrong player"),
             }
         } else {
             panic!("Cell could not be set although it shouldn't be set before");
@@ -139,11 +313,45 @@ mod test {
         original.set_cell(0, 0, PlayerId(2));
 
         let clone = original.clone();
-        assert_eq!(original.column_count, clone.column_count);
-        assert_eq!(original.row_count, clone.row_count);
+        assert_eq!(original.column_count(), clone.column_count());
+        assert_eq!(original.row_count(), clone.row_count());
         assert_eq!(original.to_win, clone.to_win);
+        assert_eq!(original.last_mover(), clone.last_mover());
         assert_eq!(original.get_cell(0, 0), clone.get_cell(0, 0));
         assert_eq!(original.get_cell(0, 1), clone.get_cell(0, 1));
         assert_eq!(original.get_cell(0, 2), clone.get_cell(0, 2));
     }
+
+    #[test]
+    fn test_set_cell_at_three_dimensions() {
+        let mut grid = Grid::with_dimensions(&[4, 4, 4], 4);
+        assert!(grid.set_cell_at(&[2, 1, 3], PlayerId(1)));
+        assert!(!grid.set_cell_at(&[2, 1, 3], PlayerId(2)));
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell_at(&[2, 1, 3]));
+        assert_eq!(CellState::Unset, *grid.get_cell_at(&[1, 2, 3]));
+        assert_eq!(vec![2, 1, 3], grid.coordinates(grid.index_of(&[2, 1, 3])));
+    }
+
+    #[test]
+    fn test_canonical_is_same_for_symmetric_grids() {
+        let mut corner = Grid::new(3, 3, 3);
+        corner.set_cell(0, 0, PlayerId(1));
+        corner.set_cell(1, 2, PlayerId(2));
+        let canonical = corner.canonical();
+        for symmetry in corner.symmetries() {
+            assert!(canonical == corner.transformed(symmetry).canonical());
+        }
+
+        let mut other = Grid::new(3, 3, 3);
+        other.set_cell(0, 0, PlayerId(1));
+        other.set_cell(1, 1, PlayerId(2));
+        assert!(canonical != other.canonical());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_cell_at_doesnt_work_out_of_bounds_layers() {
+        let mut grid = Grid::with_dimensions(&[2, 3, 3], 3);
+        grid.set_cell_at(&[2, 0, 0], PlayerId(1));
+    }
 }
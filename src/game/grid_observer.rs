@@ -1,131 +1,195 @@
 use super::grid::Grid;
-use super::{PlayerId, CellState, GameState};
-use std::iter::Iterator;
+use super::{Cell, CellState, NumberCell, PlayerId, GameState, Topology};
 
+/// The line checker of k-in-a-row: a full line of one symbol belongs to the symbol's player.
 pub fn check_winner(grid: &Grid) -> GameState {
-    match check_horizontal(&grid)
-            .or(check_vertical(&grid))
-            .or(check_diagonal(&grid)) {
-        Some(id) => GameState::Win(id),
-        None => {
-            if check_full(&grid) {
-                GameState::Draw
-            } else {
-                GameState::Mid
+    for line in grid.lines() {
+        if let CellState::Set(symbol) = *grid.get_cell_by_index(line[0]) {
+            if line.iter().all(|&index| *grid.get_cell_by_index(index) == CellState::Set(symbol)) {
+                return GameState::Win(symbol);
             }
         }
     }
+    if check_full(grid) {
+        GameState::Draw
+    } else {
+        GameState::Mid
+    }
 }
 
-fn check_horizontal(grid: &Grid) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
-    for row_nr in 0..grid.row_count {
-        for cell_nr in 0 .. grid.column_count {
-            let cell = grid.get_cell(row_nr, cell_nr);
-            check_cell(&cell, &mut streak_player, &mut streak_length);
-            if streak_length >= grid.to_win {
-                return Some(PlayerId(streak_player));
+/// The line checker of numerical tic-tac-toe: a full line adding up to the winning sum
+/// belongs to the player who completed it.
+pub fn check_sum(grid: &Grid<NumberCell>) -> GameState {
+    let target = winning_sum(grid);
+    for line in grid.lines() {
+        let mut sum = 0;
+        let mut full = true;
+        for &index in line.iter() {
+            match *grid.get_cell_by_index(index) {
+                NumberCell::Set(number) => sum += number,
+                NumberCell::Unset => {
+                    full = false;
+                    break;
+                }
             }
         }
-        streak_length = 0;
+        if full && sum == target {
+            let player = grid.last_mover().expect("a line can't be full before the first move");
+            return GameState::Win(player);
+        }
+    }
+    if check_full(grid) {
+        GameState::Draw
+    } else {
+        GameState::Mid
     }
-    None
 }
 
-fn check_vertical(grid: &Grid) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
+/// The sum a line needs in numerical tic-tac-toe, 15 on a 3x3 grid.
+/// This is the magic constant for the numbers 1 to the cell count.
+pub fn winning_sum(grid: &Grid<NumberCell>) -> u32 {
+    grid.to_win * (grid.cell_count() as u32 + 1) / 2
+}
+
+/// The numbers `player` may still place in numerical tic-tac-toe.
+/// The first player places odd numbers, the second one even numbers.
+pub fn available_numbers(grid: &Grid<NumberCell>, player: PlayerId) -> Vec<u32> {
+    let PlayerId(id) = player;
+    (1 .. grid.cell_count() as u32 + 1)
+        .filter(|number| number % 2 == id % 2)
+        .filter(|&number| !(0 .. grid.cell_count())
+            .any(|index| *grid.get_cell_by_index(index) == NumberCell::Set(number)))
+        .collect()
+}
 
-    for col_nr in 0 .. grid.column_count {
-        for row_nr in 0..grid.row_count {
-            let cell = &grid.get_cell(row_nr, col_nr);
-            check_cell(cell, &mut streak_player, &mut streak_length);
-            if streak_length >= grid.to_win {
-                return Some(PlayerId(streak_player));
+/// Lists every run of `to_win` cells in a grid with the given dimensions, as cell indices.
+/// Runs go along all axes and all diagonals, which are 4 directions in 2D and 13 in 3D.
+/// On a toroidal grid runs may wrap around the edges, but never visit a cell twice.
+pub fn lines(dimensions: &[usize], to_win: u32, topology: Topology) -> Vec<Box<[usize]>> {
+    let cell_count: usize = dimensions.iter().product();
+    let mut lines = Vec::new();
+    // without any cells to complete there are no lines
+    if to_win == 0 {
+        return lines;
+    }
+    if to_win == 1 {
+        // a single cell lies on a line in every direction, so only count it once
+        for index in 0 .. cell_count {
+            lines.push(vec![index].into_boxed_slice());
+        }
+        return lines;
+    }
+    for direction in directions(dimensions.len()) {
+        for start in 0 .. cell_count {
+            let line = match topology {
+                Topology::Bounded => line_from(dimensions, start, &direction, to_win as usize),
+                Topology::Toroidal => wrapping_line_from(dimensions, start, &direction, to_win as usize),
+            };
+            if let Some(line) = line {
+                lines.push(line);
             }
         }
-        streak_length = 0;
     }
-    None
+    lines
 }
 
-fn check_diagonal(grid: &Grid) -> Option<PlayerId> {
-    for rownr in 0 .. grid.row_count {
-        match check_any_diagonal(&grid, rownr, 0) {
-            None => continue,
-            Some(PlayerId(id)) => return Some(PlayerId(id)),
+// Every direction vector with components in {-1, 0, 1}, excluding the zero vector.
+// Only one of each pair of opposite directions is included (the first non-zero component is 1).
+fn directions(dimension_count: usize) -> Vec<Vec<isize>> {
+    let mut directions = Vec::new();
+    for mut code in 0 .. 3usize.pow(dimension_count as u32) {
+        let mut direction = Vec::with_capacity(dimension_count);
+        for _ in 0 .. dimension_count {
+            direction.push(match code % 3 {
+                0 => 0,
+                1 => 1,
+                _ => -1,
+            });
+            code /= 3;
         }
-    }
-    for colnr in 0 .. grid.column_count {
-        match check_any_diagonal(&grid, 0, colnr) {
-            None => continue,
-            Some(PlayerId(id)) => return Some(PlayerId(id)),
+        direction.reverse();
+        if direction.iter().find(|&&component| component != 0) == Some(&1) {
+            directions.push(direction);
         }
     }
-    None
+    directions
 }
 
-fn check_any_diagonal(grid: &Grid, startrow: usize, startcolumn: usize) -> Option<PlayerId> {
-    check_top_down_diagonal(grid, startrow, startcolumn)
-        .or(check_bottom_up_diagonal(grid, startrow, startcolumn))
-}
+fn line_from(dimensions: &[usize], start: usize, direction: &[isize], length: usize)
+    -> Option<Box<[usize]>> {
+    let start_coordinates = coordinates_of(dimensions, start);
 
-fn check_top_down_diagonal(grid: &Grid, startrow: usize, startcolumn: usize) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
-
-    let mut rownr = startrow;
-    let mut colnr = startcolumn;
-    while (rownr < grid.row_count) & (colnr < grid.column_count) {
-        let cell = &grid.get_cell(rownr, colnr);
-        check_cell(cell, &mut streak_player, &mut streak_length);
-        if streak_length >= grid.to_win {
-            return Some(PlayerId(streak_player));
+    let mut line = Vec::with_capacity(length);
+    for step in 0 .. length as isize {
+        let mut index = 0;
+        for ((&coordinate, &component), &size) in start_coordinates.iter()
+                .zip(direction.iter())
+                .zip(dimensions.iter()) {
+            let coordinate = coordinate as isize + component * step;
+            if coordinate < 0 || coordinate >= size as isize {
+                return None;
+            }
+            index = index * size + coordinate as usize;
         }
-        colnr += 1;
-        rownr += 1
+        line.push(index);
     }
-    None
+    Some(line.into_boxed_slice())
 }
 
-fn check_bottom_up_diagonal(grid: &Grid, startrow: usize, startcolumn: usize) -> Option<PlayerId> {
-    let mut streak_player = 0;
-    let mut streak_length = 0;
+fn wrapping_line_from(dimensions: &[usize], start: usize, direction: &[isize], length: usize)
+    -> Option<Box<[usize]>> {
+    let start_coordinates = coordinates_of(dimensions, start);
+    let moving_axes: Vec<usize> = (0 .. dimensions.len())
+        .filter(|&axis| direction[axis] != 0)
+        .collect();
 
-    let cols_iter = startcolumn .. grid.column_count;
-    let rows_iter = (0 .. startrow + 1).rev();
+    // the line comes back to its first cell once every moving axis went around the board,
+    // after the least common multiple of their sizes, so a longer line would reuse cells
+    let cycle = moving_axes.iter().fold(1, |cycle, &axis| lcm(cycle, dimensions[axis]));
+    if cycle < length {
+        return None;
+    }
 
-    for (colnr, rownr) in cols_iter.zip(rows_iter) {
-        let cell = &grid.get_cell(rownr, colnr);
-        check_cell(cell, &mut streak_player, &mut streak_length);
-        if streak_length >= grid.to_win {
-            return Some(PlayerId(streak_player));
+    let mut line = Vec::with_capacity(length);
+    for step in 0 .. length as isize {
+        let mut index = 0;
+        for ((&coordinate, &component), &size) in start_coordinates.iter()
+                .zip(direction.iter())
+                .zip(dimensions.iter()) {
+            let coordinate = (coordinate as isize + component * step).rem_euclid(size as isize);
+            index = index * size + coordinate as usize;
         }
+        line.push(index);
     }
-    None
+    // if the line exactly fills a full loop around the board, every cell of the loop
+    // would start the same line, so only start it at the lowest index of the loop
+    if cycle == length && line.iter().min() != Some(&line[0]) {
+        return None;
+    }
+    Some(line.into_boxed_slice())
 }
 
-fn check_cell(cell: &CellState, streak_player: &mut u32, streak_length: &mut u32) {
-    match cell {
-        &CellState::Unset => *streak_length = 0,
-        &CellState::Set(PlayerId(id)) if id == *streak_player => *streak_length += 1,
-        &CellState::Set(PlayerId(id)) => {
-            *streak_length = 1;
-            *streak_player = id;
-        }
+fn lcm(first: usize, second: usize) -> usize {
+    let (mut a, mut b) = (first, second);
+    while b != 0 {
+        let rest = a % b;
+        a = b;
+        b = rest;
     }
+    first / a * second
 }
 
-fn check_full(grid: &Grid) -> bool {
-    for row_nr in 0 .. grid.row_count {
-        for col_nr in 0 .. grid.column_count {
-            if *grid.get_cell(row_nr, col_nr) == CellState::Unset {
-                return false;
-            }
-        }
+pub fn coordinates_of(dimensions: &[usize], mut index: usize) -> Vec<usize> {
+    let mut coordinates = vec![0; dimensions.len()];
+    for (coordinate, &size) in coordinates.iter_mut().zip(dimensions.iter()).rev() {
+        *coordinate = index % size;
+        index /= size;
     }
-    true
+    coordinates
+}
+
+pub fn check_full<C: Cell>(grid: &Grid<C>) -> bool {
+    (0 .. grid.cell_count()).all(|index| *grid.get_cell_by_index(index) != C::unset())
 }
 
 
@@ -135,7 +199,7 @@ fn check_full(grid: &Grid) -> bool {
 mod test {
     use super::*;
     use ::game::grid::Grid;
-    use ::game::PlayerId;
+    use ::game::{NumberCell, PlayerId};
 
     #[test]
     #[should_panic]
@@ -267,4 +331,120 @@ mod test {
         grid.set_cell(4, 8, PlayerId(1));
         assert!(check_winner(&grid).is_none());
     }
+
+    #[test]
+    fn test_check_sum_line_of_fifteen_wins() {
+        let mut grid = Grid::<NumberCell>::create(&[3, 3], 3, Topology::Bounded);
+        grid.place(0, NumberCell::Set(9), PlayerId(1));
+        grid.place(1, NumberCell::Set(2), PlayerId(2));
+        grid.place(8, NumberCell::Set(1), PlayerId(1));
+        assert!(check_sum(&grid).is_none());
+        grid.place(2, NumberCell::Set(4), PlayerId(2));
+        match check_sum(&grid) {
+            GameState::Win(PlayerId(2)) => {},
+            _ => panic!("9 + 2 + 4 in a line should win for the player placing the 4"),
+        }
+    }
+
+    #[test]
+    fn test_available_numbers() {
+        let mut grid = Grid::<NumberCell>::create(&[3, 3], 3, Topology::Bounded);
+        assert_eq!(15, winning_sum(&grid));
+        grid.place(0, NumberCell::Set(3), PlayerId(1));
+        grid.place(1, NumberCell::Set(8), PlayerId(2));
+        assert_eq!(vec![1, 5, 7, 9], available_numbers(&grid, PlayerId(1)));
+        assert_eq!(vec![2, 4, 6], available_numbers(&grid, PlayerId(2)));
+    }
+
+    #[test]
+    fn test_lines_count_matches_known_values() {
+        assert_eq!(8, lines(&[3, 3], 3, Topology::Bounded).len());
+        assert_eq!(49, lines(&[3, 3, 3], 3, Topology::Bounded).len());
+        assert_eq!(76, lines(&[4, 4, 4], 4, Topology::Bounded).len());
+    }
+
+    #[test]
+    fn test_no_lines_without_cells_to_win() {
+        assert!(lines(&[3, 3], 0, Topology::Bounded).is_empty());
+        assert!(lines(&[3, 3], 0, Topology::Toroidal).is_empty());
+        let mut grid = Grid::new(3, 3, 0);
+        assert!(check_winner(&grid).is_none());
+        grid.set_cell(1, 1, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        assert_eq!(9, lines(&[3, 3], 1, Topology::Bounded).len());
+    }
+
+    #[test]
+    fn test_toroidal_lines_are_not_counted_twice() {
+        assert_eq!(12, lines(&[3, 3], 3, Topology::Toroidal).len());
+        // rows of 2 columns are too short to hold a line of 3 without reusing a cell, but the
+        // diagonals only come back to their first cell after 6 steps
+        assert_eq!(2 + 2 * 6, lines(&[3, 2], 3, Topology::Toroidal).len());
+        assert_eq!(4 * 16, lines(&[4, 4], 3, Topology::Toroidal).len());
+        // the same for longer lines: 4 in a row fit in the 15 cells of a diagonal of 3x5
+        assert_eq!(15 + 2 * 15, lines(&[3, 5], 4, Topology::Toroidal).len());
+    }
+
+    #[test]
+    fn test_check_winner_wraps_on_toroidal_grid() {
+        let mut grid = Grid::with_topology(&[5, 5], 3, Topology::Toroidal);
+        grid.set_cell(2, 4, PlayerId(1));
+        grid.set_cell(2, 0, PlayerId(1));
+        grid.set_cell(2, 1, PlayerId(1));
+        assert!(check_winner(&grid).is_some());
+
+        let mut grid = Grid::with_topology(&[5, 5], 3, Topology::Toroidal);
+        grid.set_cell(3, 0, PlayerId(1));
+        grid.set_cell(4, 1, PlayerId(1));
+        grid.set_cell(0, 2, PlayerId(1));
+        assert!(check_winner(&grid).is_some());
+
+        let mut grid = Grid::with_topology(&[5, 5], 3, Topology::Toroidal);
+        grid.set_cell(1, 4, PlayerId(1));
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(4, 1, PlayerId(1));
+        assert!(check_winner(&grid).is_some());
+
+        // on a board that isn't square the diagonal wraps around both edges at different steps
+        let mut grid = Grid::with_topology(&[3, 5], 4, Topology::Toroidal);
+        grid.set_cell(1, 3, PlayerId(1));
+        grid.set_cell(2, 4, PlayerId(1));
+        grid.set_cell(0, 0, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        grid.set_cell(1, 1, PlayerId(1));
+        assert!(check_winner(&grid).is_some());
+    }
+
+    #[test]
+    fn test_check_winner_doesnt_wrap_on_bounded_grid() {
+        let mut grid = Grid::new(5, 5, 3);
+        grid.set_cell(2, 4, PlayerId(1));
+        grid.set_cell(2, 0, PlayerId(1));
+        grid.set_cell(2, 1, PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+    }
+
+    #[test]
+    fn test_check_winner_through_layers() {
+        let mut grid = Grid::with_dimensions(&[4, 4, 4], 4);
+        grid.set_cell_at(&[0, 2, 1], PlayerId(1));
+        grid.set_cell_at(&[1, 2, 1], PlayerId(1));
+        grid.set_cell_at(&[2, 2, 1], PlayerId(1));
+        assert!(check_winner(&grid).is_none());
+        grid.set_cell_at(&[3, 2, 1], PlayerId(1));
+        assert!(check_winner(&grid).is_some());
+    }
+
+    #[test]
+    fn test_check_winner_space_diagonal() {
+        let mut grid = Grid::with_dimensions(&[4, 4, 4], 4);
+        grid.set_cell_at(&[3, 0, 3], PlayerId(2));
+        grid.set_cell_at(&[2, 1, 2], PlayerId(2));
+        grid.set_cell_at(&[1, 2, 1], PlayerId(2));
+        grid.set_cell_at(&[0, 3, 0], PlayerId(2));
+        match check_winner(&grid) {
+            GameState::Win(PlayerId(2)) => {},
+            _ => panic!("The space diagonal wasn't detected"),
+        }
+    }
 }
@@ -1,55 +1,175 @@
+use std::fmt;
+use std::hash::Hash;
+
 #[derive(Debug)]
 #[derive(Copy, Clone)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq)]
+#[derive(PartialOrd, Ord)]
+#[derive(Hash)]
 pub struct PlayerId(pub u32);
 
+/// The number of players taking turns, with the ids 1 to `PLAYER_COUNT`.
+pub const PLAYER_COUNT: u32 = 2;
+
+/// The content of a single cell, e.g. a player's symbol or a number.
+/// Cells are ordered and hashable so positions can be compared and cached by their canonical form.
+pub trait Cell: Copy + Eq + Ord + Hash + fmt::Display {
+    fn unset() -> Self;
+
+    /// A number for storing the cell, 0 for unset cells.
+    fn code(&self) -> u32;
+
+    /// Reads a cell written as a single character, e.g. in a position like "x.o/.x./..o".
+    fn parse(symbol: char) -> Option<Self>;
+
+    /// The character `parse` reads back into the cell.
+    fn symbol(&self) -> char;
+}
+
+/// The symbols any player may place in wild games.
+pub const WILD_SYMBOLS: [PlayerId; 2] = [PlayerId(1), PlayerId(2)];
+
 #[derive(Debug)]
 #[derive(Copy, Clone)]
+#[derive(PartialEq, Eq)]
+#[derive(PartialOrd, Ord)]
+#[derive(Hash)]
 pub enum CellState {
     Set(PlayerId),
     Unset,
 }
 
+/// A cell of numerical tic-tac-toe, where one player places odd and the other even numbers.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq, Eq)]
+#[derive(PartialOrd, Ord)]
+#[derive(Hash)]
+pub enum NumberCell {
+    Set(u32),
+    Unset,
+}
+
+/// How the edges of a grid behave for lines.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub enum Topology {
+    Bounded,
+    // lines wrap around the edges, so a streak can continue from the last column into the first
+    Toroidal,
+}
+
 pub enum GameState {
     Win(PlayerId),
+    // the player completed a line in a misère game, which loses
+    Lose(PlayerId),
+    // the player ran out of time on the clock
+    LossOnTime(PlayerId),
     Draw,
     Mid,
 }
 
-impl PartialEq for CellState {
-    fn eq(&self, other: &CellState) -> bool {
+impl fmt::Display for CellState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            CellState::Unset => {
-                match *other {
-                    CellState::Unset => true,
-                    _ => false,
-                }
-            },
-            CellState::Set(PlayerId(own_id)) => {
-                match *other {
-                    CellState::Unset => false,
-                    CellState::Set(PlayerId(other_id)) if other_id == own_id => true,
-                    _ => false,
-                }
-            }
+            CellState::Unset => write!(f, "_"),
+            CellState::Set(PlayerId(id)) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl Cell for CellState {
+    fn unset() -> CellState {
+        CellState::Unset
+    }
+
+    fn code(&self) -> u32 {
+        match *self {
+            CellState::Unset => 0,
+            CellState::Set(PlayerId(id)) => id,
+        }
+    }
+
+    fn parse(symbol: char) -> Option<CellState> {
+        match symbol {
+            '.' | '_' => Some(CellState::Unset),
+            'x' | 'X' | '1' => Some(CellState::Set(PlayerId(1))),
+            'o' | 'O' | '2' => Some(CellState::Set(PlayerId(2))),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match *self {
+            CellState::Unset => '.',
+            CellState::Set(PlayerId(1)) => 'x',
+            CellState::Set(PlayerId(2)) => 'o',
+            CellState::Set(PlayerId(id)) => std::char::from_digit(id, 10).unwrap_or('?'),
+        }
+    }
+}
+
+impl fmt::Display for NumberCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NumberCell::Unset => write!(f, "_"),
+            NumberCell::Set(number) => write!(f, "{}", number),
+        }
+    }
+}
+
+impl Cell for NumberCell {
+    fn unset() -> NumberCell {
+        NumberCell::Unset
+    }
+
+    fn code(&self) -> u32 {
+        match *self {
+            NumberCell::Unset => 0,
+            NumberCell::Set(number) => number,
+        }
+    }
+
+    fn parse(symbol: char) -> Option<NumberCell> {
+        match symbol {
+            '.' | '_' => Some(NumberCell::Unset),
+            '1' ..= '9' => symbol.to_digit(10).map(NumberCell::Set),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match *self {
+            NumberCell::Unset => '.',
+            NumberCell::Set(number) => std::char::from_digit(number, 10).unwrap_or('?'),
         }
     }
 }
 
 impl GameState {
-    fn is_some(&self) -> bool {
+    pub fn is_some(&self) -> bool {
         match *self {
-            GameState::Draw => false,
-            GameState::Mid => false,
-            _ => true,
-        } 
+            GameState::Win(_) | GameState::Lose(_) | GameState::LossOnTime(_) => true,
+            GameState::Draw | GameState::Mid => false,
+        }
     }
 
-    fn is_none(&self) -> bool {
+    pub fn is_none(&self) -> bool {
         !self.is_some()
     }
+
+    /// Whether the game has ended, in whatever way.
+    pub fn is_over(&self) -> bool {
+        !matches!(*self, GameState::Mid)
+    }
 }
 
 
+pub mod bitboard;
+pub mod clock;
 pub mod grid;
 pub mod grid_observer;
+pub mod position;
+pub mod rules;
+pub mod symmetry;
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use super::grid::Grid;
+use super::rules::Rules;
+use super::{Cell, PlayerId, GameState, Topology};
+
+/// Sets up a position from a string like "x.o/.x./..o", with the rows separated by '/' and
+/// the layers of 3D boards by '|'. The player to move may follow, e.g. "x.o/.x./..o o".
+/// Returns the grid and the player to move, or why the position can't come up in a game.
+pub fn setup<R: Rules>(rules: &R, text: &str, to_win: u32, topology: Topology)
+    -> Result<(Grid<R::Cell>, PlayerId), String> {
+    let (grid, to_move) = parse::<R::Cell>(text, to_win, topology)?;
+    let (grid, player) = replay(rules, &grid)?;
+    match to_move {
+        Some(to_move) if to_move != player => {
+            let (PlayerId(id), PlayerId(expected)) = (to_move, player);
+            Err(format!("it can't be the turn of player {}, player {} is to move", id, expected))
+        },
+        _ => Ok((grid, player)),
+    }
+}
+
+/// Reads the cells of a position and the player to move, if given, without any checks
+/// whether the position can come up in a game.
+pub fn parse<C: Cell>(text: &str, to_win: u32, topology: Topology) -> Result<(Grid<C>, Option<PlayerId>), String> {
+    let mut parts = text.split_whitespace();
+    let board = parts.next().ok_or("the position is empty")?;
+    let to_move = match parts.next() {
+        Some("x") | Some("X") | Some("1") => Some(PlayerId(1)),
+        Some("o") | Some("O") | Some("2") => Some(PlayerId(2)),
+        Some(player) => return Err(format!("'{}' is no player", player)),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return Err(format!("unexpected text after the position '{}'", text));
+    }
+
+    let layers: Vec<Vec<&str>> = board.split('|').map(|layer| layer.split('/').collect()).collect();
+    let rows = layers[0].len();
+    let columns = layers[0][0].chars().count();
+    let mut cells = Vec::new();
+    for layer in &layers {
+        if layer.len() != rows {
+            return Err("every layer needs the same number of rows".to_string());
+        }
+        for row in layer {
+            if row.chars().count() != columns || columns == 0 {
+                return Err("every row needs the same number of cells".to_string());
+            }
+            for symbol in row.chars() {
+                cells.push(C::parse(symbol).ok_or_else(|| format!("'{}' is no cell", symbol))?);
+            }
+        }
+    }
+
+    let dimensions = if layers.len() > 1 { vec![layers.len(), rows, columns] } else { vec![rows, columns] };
+    let mut grid = Grid::create(&dimensions, to_win, topology);
+    for (index, cell) in cells.into_iter().enumerate() {
+        if cell != C::unset() {
+            // the movers are only known after replaying the position
+            grid.place(index, cell, PlayerId(1));
+        }
+    }
+    Ok((grid, to_move))
+}
+
+/// Writes the grid the way `setup` reads it, followed by the player to move, e.g. "x.o/.x./..o x".
+pub fn describe<C: Cell>(grid: &Grid<C>, to_move: PlayerId) -> String {
+    let columns = grid.column_count();
+    let layer_size = grid.row_count() * columns;
+    let layers: Vec<String> = (0 .. grid.cell_count()).step_by(layer_size).map(|layer_start| {
+        let rows: Vec<String> = (layer_start .. layer_start + layer_size).step_by(columns)
+            .map(|row_start| (row_start .. row_start + columns)
+                .map(|index| grid.get_cell_by_index(index).symbol())
+                .collect())
+            .collect();
+        rows.join("/")
+    }).collect();
+    let player = match to_move {
+        PlayerId(1) => "x".to_string(),
+        PlayerId(2) => "o".to_string(),
+        PlayerId(id) => id.to_string(),
+    };
+    format!("{} {}", layers.join("|"), player)
+}
+
+// Finds an order of moves starting with player 1 that leads to the position without the game
+// ending early. Returns the grid reached that way, which knows the last mover, and the player to move.
+fn replay<R: Rules>(rules: &R, target: &Grid<R::Cell>) -> Result<(Grid<R::Cell>, PlayerId), String> {
+    let mut stones: Vec<_> = (0 .. target.cell_count())
+        .filter(|&index| *target.get_cell_by_index(index) != R::Cell::unset())
+        .map(|index| (index, *target.get_cell_by_index(index)))
+        .collect();
+    let start = Grid::create(target.dimensions(), target.to_win, target.topology());
+    let mut dead_ends = HashSet::new();
+    let mut player = PlayerId(1);
+    for _ in 0 .. stones.len() {
+        player = rules.next_player(player);
+    }
+    match replay_from(rules, start, PlayerId(1), &mut stones, &mut dead_ends) {
+        Some(grid) => Ok((grid, player)),
+        None => Err("the position can't come up in a game".to_string()),
+    }
+}
+
+fn replay_from<R: Rules>(rules: &R, grid: Grid<R::Cell>, player: PlayerId, stones: &mut Vec<(usize, R::Cell)>,
+                         dead_ends: &mut HashSet<Grid<R::Cell>>) -> Option<Grid<R::Cell>> {
+    if stones.is_empty() {
+        return Some(grid);
+    }
+    if dead_ends.contains(&grid) {
+        return None;
+    }
+    for position in 0 .. stones.len() {
+        let (index, cell) = stones[position];
+        if rules.owner(&cell).is_some_and(|owner| owner != player) {
+            continue;
+        }
+        let placed = rules.legal_moves(&grid, player).into_iter().find_map(|mov| {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, &mov);
+            if *new_grid.get_cell_by_index(index) == cell { Some(new_grid) } else { None }
+        });
+        let new_grid = match placed {
+            Some(new_grid) => new_grid,
+            None => continue,
+        };
+        // only the last move may end the game
+        match rules.game_state(&new_grid) {
+            GameState::Mid => {},
+            _ if stones.len() == 1 => {},
+            _ => continue,
+        }
+        stones.swap_remove(position);
+        let replayed = replay_from(rules, new_grid, rules.next_player(player), stones, dead_ends);
+        stones.push((index, cell));
+        let last = stones.len() - 1;
+        stones.swap(position, last);
+        if replayed.is_some() {
+            return replayed;
+        }
+    }
+    dead_ends.insert(grid);
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::rules::{LineRules, WildRules, NumericalRules};
+    use ::game::{CellState, NumberCell, PlayerId, Topology};
+
+    #[test]
+    fn test_setup_from_string() {
+        let (grid, player) = setup(&LineRules::default(), "x.o/.x./..o", 3, Topology::Bounded).unwrap();
+        assert_eq!(&[3, 3], grid.dimensions());
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(0, 0));
+        assert_eq!(CellState::Set(PlayerId(2)), *grid.get_cell(0, 2));
+        assert_eq!(CellState::Unset, *grid.get_cell(0, 1));
+        assert_eq!(PlayerId(1), player);
+        assert_eq!(Some(PlayerId(2)), grid.last_mover());
+
+        let (grid, player) = setup(&LineRules::default(), "x../.../... o", 3, Topology::Bounded).unwrap();
+        assert_eq!(PlayerId(2), player);
+        assert_eq!(9, grid.cell_count());
+    }
+
+    #[test]
+    fn test_describe_reads_back() {
+        let rules = LineRules::default();
+        for &(text, to_win) in &[("x.o/.x./..o x", 3), ("x../.../... o", 3), ("x./..|../.o x", 2)] {
+            let (grid, player) = setup(&rules, text, to_win, Topology::Bounded).unwrap();
+            assert_eq!(text, describe(&grid, player));
+        }
+        let (grid, player) = setup(&NumericalRules::default(), "52./.../...", 3, Topology::Bounded).unwrap();
+        assert_eq!("52./.../... x", describe(&grid, player));
+    }
+
+    #[test]
+    fn test_setup_in_three_dimensions() {
+        let (grid, _) = setup(&LineRules::default(), "x./..|../.o", 2, Topology::Bounded).unwrap();
+        assert_eq!(&[2, 2, 2], grid.dimensions());
+        assert_eq!(CellState::Set(PlayerId(2)), *grid.get_cell_at(&[1, 1, 1]));
+    }
+
+    #[test]
+    fn test_unreachable_positions_are_refused() {
+        let rules = LineRules::default();
+        // too many stones of one player
+        assert!(setup(&rules, "xx./.../...", 3, Topology::Bounded).is_err());
+        assert!(setup(&rules, "o../.../...", 3, Topology::Bounded).is_err());
+        // both players completed a line
+        assert!(setup(&rules, "xxx/ooo/x..", 3, Topology::Bounded).is_err());
+        // the game went on after a line was completed
+        assert!(setup(&rules, "xxx/oo./xoo", 3, Topology::Bounded).is_err());
+        // the wrong player to move
+        assert!(setup(&rules, "x../.../... x", 3, Topology::Bounded).is_err());
+        // a finished game is fine, with the line completed by the last move
+        assert!(setup(&rules, "xxx/oo./...", 3, Topology::Bounded).is_ok());
+    }
+
+    #[test]
+    fn test_malformed_positions_are_refused() {
+        let rules = LineRules::default();
+        assert!(setup(&rules, "x.o/.x/..o", 3, Topology::Bounded).is_err());
+        assert!(setup(&rules, "x.a/.../...", 3, Topology::Bounded).is_err());
+        assert!(setup(&rules, "", 3, Topology::Bounded).is_err());
+        assert!(setup(&rules, "x../.../... z", 3, Topology::Bounded).is_err());
+    }
+
+    #[test]
+    fn test_setup_other_variants() {
+        let (grid, player) = setup(&WildRules::default(), "xx./.../...", 3, Topology::Bounded).unwrap();
+        assert_eq!(PlayerId(1), player);
+        assert_eq!(Some(PlayerId(2)), grid.last_mover());
+
+        let (grid, player) = setup(&NumericalRules::default(), "52./.../...", 3, Topology::Bounded).unwrap();
+        assert_eq!(NumberCell::Set(5), *grid.get_cell(0, 0));
+        assert_eq!(PlayerId(1), player);
+        // every number may be used only once
+        assert!(setup(&NumericalRules::default(), "52./5../4..", 3, Topology::Bounded).is_err());
+    }
+}
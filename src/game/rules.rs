@@ -0,0 +1,304 @@
+use super::bitboard::BitBoard;
+use super::grid::Grid;
+use super::grid_observer;
+use super::{Cell, CellState, NumberCell, PlayerId, GameState, PLAYER_COUNT, WILD_SYMBOLS};
+
+/// Everything that makes up a variant of the game: which moves are legal, what they do,
+/// when the game is over and who plays next.
+pub trait Rules {
+    type Cell: Cell;
+    type Move: Clone + PartialEq;
+
+    /// Every move `player` may make on `grid`.
+    fn legal_moves(&self, grid: &Grid<Self::Cell>, player: PlayerId) -> Vec<Self::Move>;
+
+    /// Makes the move for `player`. Illegal moves return false and leave the grid unchanged.
+    fn apply_move(&self, grid: &mut Grid<Self::Cell>, player: PlayerId, mov: &Self::Move) -> bool;
+
+    fn game_state(&self, grid: &Grid<Self::Cell>) -> GameState;
+
+    fn next_player(&self, player: PlayerId) -> PlayerId {
+        let PlayerId(id) = player;
+        PlayerId(id % PLAYER_COUNT + 1)
+    }
+
+    /// The way a move is entered in the terminal, e.g. "0 2", or "0 2 7" to place a 7.
+    fn describe_move(&self, grid: &Grid<Self::Cell>, mov: &Self::Move) -> String;
+
+    /// The name of the variant, e.g. to check which rules a stored opening book was made for.
+    fn name(&self) -> &'static str;
+
+    /// The player who placed `cell`, if that can be told from the cell alone.
+    fn owner(&self, _cell: &Self::Cell) -> Option<PlayerId> {
+        None
+    }
+
+    /// The classic rules if the variant is played by them, so the AI can search it on bitboards.
+    fn line_rules(&self) -> Option<LineRules> {
+        None
+    }
+
+    /// The index of the cell `mov` places on, if that can be told without making the move.
+    fn move_cell(&self, _mov: &Self::Move) -> Option<usize> {
+        None
+    }
+}
+
+/// The move of all current variants: putting `cell` into the unset cell at `index`.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub struct Placement<C: Cell> {
+    pub index: usize,
+    pub cell: C,
+}
+
+impl<C: Cell> Placement<C> {
+    pub fn new(index: usize, cell: C) -> Placement<C> {
+        Placement {
+            index,
+            cell,
+        }
+    }
+}
+
+/// Classic k-in-a-row: players place their own symbol and a line of `to_win` of them wins.
+#[derive(Default)]
+#[derive(Copy, Clone)]
+pub struct LineRules {
+    // the player who completes a line loses instead of winning
+    pub misere: bool,
+}
+
+impl LineRules {
+    /// The same as `game_state` on the grid of the board.
+    pub fn bitboard_state(&self, board: &BitBoard) -> GameState {
+        apply_misere(board.check_winner(), self.misere)
+    }
+}
+
+/// Wild tic-tac-toe: every player may place any symbol, and a line belongs to whoever completes it.
+#[derive(Default)]
+#[derive(Copy, Clone)]
+pub struct WildRules {
+    pub misere: bool,
+}
+
+/// Numerical tic-tac-toe: the first player places odd numbers, the second one even numbers,
+/// each at most once, and a full line adding up to the magic sum belongs to whoever completes it.
+#[derive(Default)]
+#[derive(Copy, Clone)]
+pub struct NumericalRules {
+    pub misere: bool,
+}
+
+impl Rules for LineRules {
+    type Cell = CellState;
+    type Move = Placement<CellState>;
+
+    fn legal_moves(&self, grid: &Grid, player: PlayerId) -> Vec<Placement<CellState>> {
+        grid.empty_cells()
+            .map(|index| Placement::new(index, CellState::Set(player)))
+            .collect()
+    }
+
+    fn apply_move(&self, grid: &mut Grid, player: PlayerId, mov: &Placement<CellState>) -> bool {
+        mov.cell == CellState::Set(player) && grid.place(mov.index, mov.cell, player)
+    }
+
+    fn game_state(&self, grid: &Grid) -> GameState {
+        apply_misere(grid_observer::check_winner(grid), self.misere)
+    }
+
+    fn describe_move(&self, grid: &Grid, mov: &Placement<CellState>) -> String {
+        describe_coordinates(grid, mov.index)
+    }
+
+    fn name(&self) -> &'static str {
+        if self.misere { "misere classic" } else { "classic" }
+    }
+
+    fn move_cell(&self, mov: &Placement<CellState>) -> Option<usize> {
+        Some(mov.index)
+    }
+
+    fn owner(&self, cell: &CellState) -> Option<PlayerId> {
+        match *cell {
+            CellState::Set(player) => Some(player),
+            CellState::Unset => None,
+        }
+    }
+
+    fn line_rules(&self) -> Option<LineRules> {
+        Some(*self)
+    }
+}
+
+impl Rules for WildRules {
+    type Cell = CellState;
+    type Move = Placement<CellState>;
+
+    fn legal_moves(&self, grid: &Grid, _player: PlayerId) -> Vec<Placement<CellState>> {
+        let mut moves = Vec::new();
+        for index in grid.empty_cells() {
+            for &symbol in &WILD_SYMBOLS {
+                moves.push(Placement::new(index, CellState::Set(symbol)));
+            }
+        }
+        moves
+    }
+
+    fn apply_move(&self, grid: &mut Grid, player: PlayerId, mov: &Placement<CellState>) -> bool {
+        let known_symbol = match mov.cell {
+            CellState::Set(symbol) => WILD_SYMBOLS.contains(&symbol),
+            CellState::Unset => false,
+        };
+        known_symbol && grid.place(mov.index, mov.cell, player)
+    }
+
+    fn game_state(&self, grid: &Grid) -> GameState {
+        let state = match grid_observer::check_winner(grid) {
+            GameState::Win(symbol) => GameState::Win(grid.last_mover().unwrap_or(symbol)),
+            state => state,
+        };
+        apply_misere(state, self.misere)
+    }
+
+    fn describe_move(&self, grid: &Grid, mov: &Placement<CellState>) -> String {
+        format!("{} {}", describe_coordinates(grid, mov.index), mov.cell)
+    }
+
+    fn name(&self) -> &'static str {
+        if self.misere { "misere wild" } else { "wild" }
+    }
+
+    fn move_cell(&self, mov: &Placement<CellState>) -> Option<usize> {
+        Some(mov.index)
+    }
+}
+
+impl Rules for NumericalRules {
+    type Cell = NumberCell;
+    type Move = Placement<NumberCell>;
+
+    fn legal_moves(&self, grid: &Grid<NumberCell>, player: PlayerId) -> Vec<Placement<NumberCell>> {
+        let numbers = grid_observer::available_numbers(grid, player);
+        let mut moves = Vec::new();
+        for index in grid.empty_cells() {
+            for &number in &numbers {
+                moves.push(Placement::new(index, NumberCell::Set(number)));
+            }
+        }
+        moves
+    }
+
+    fn apply_move(&self, grid: &mut Grid<NumberCell>, player: PlayerId, mov: &Placement<NumberCell>) -> bool {
+        let available = match mov.cell {
+            NumberCell::Set(number) => grid_observer::available_numbers(grid, player).contains(&number),
+            NumberCell::Unset => false,
+        };
+        available && grid.place(mov.index, mov.cell, player)
+    }
+
+    fn game_state(&self, grid: &Grid<NumberCell>) -> GameState {
+        apply_misere(grid_observer::check_sum(grid), self.misere)
+    }
+
+    fn describe_move(&self, grid: &Grid<NumberCell>, mov: &Placement<NumberCell>) -> String {
+        format!("{} {}", describe_coordinates(grid, mov.index), mov.cell)
+    }
+
+    fn name(&self) -> &'static str {
+        if self.misere { "misere numerical" } else { "numerical" }
+    }
+
+    fn move_cell(&self, mov: &Placement<NumberCell>) -> Option<usize> {
+        Some(mov.index)
+    }
+
+    fn owner(&self, cell: &NumberCell) -> Option<PlayerId> {
+        match *cell {
+            NumberCell::Set(number) if number % 2 == 1 => Some(PlayerId(1)),
+            NumberCell::Set(_) => Some(PlayerId(2)),
+            NumberCell::Unset => None,
+        }
+    }
+}
+
+fn apply_misere(state: GameState, misere: bool) -> GameState {
+    match state {
+        GameState::Win(player) if misere => GameState::Lose(player),
+        state => state,
+    }
+}
+
+fn describe_coordinates<C: Cell>(grid: &Grid<C>, index: usize) -> String {
+    let coordinates: Vec<_> = grid.coordinates(index).iter()
+        .map(|coordinate| coordinate.to_string())
+        .collect();
+    coordinates.join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::{CellState, NumberCell, PlayerId, Topology};
+
+    #[test]
+    fn test_next_player_takes_turns() {
+        let rules = LineRules::default();
+        assert_eq!(PlayerId(2), rules.next_player(PlayerId(1)));
+        assert_eq!(PlayerId(1), rules.next_player(PlayerId(2)));
+    }
+
+    #[test]
+    fn test_line_rules_only_allow_own_symbol() {
+        let rules = LineRules::default();
+        let mut grid = Grid::new(3, 3, 3);
+        assert!(!rules.apply_move(&mut grid, PlayerId(1), &Placement::new(0, CellState::Set(PlayerId(2)))));
+        assert!(rules.apply_move(&mut grid, PlayerId(1), &Placement::new(0, CellState::Set(PlayerId(1)))));
+        assert!(!rules.apply_move(&mut grid, PlayerId(2), &Placement::new(0, CellState::Set(PlayerId(2)))));
+        assert_eq!(8, rules.legal_moves(&grid, PlayerId(2)).len());
+        assert_eq!("1 2", rules.describe_move(&grid, &Placement::new(5, CellState::Set(PlayerId(2)))));
+        assert_eq!(Some(5), rules.move_cell(&Placement::new(5, CellState::Set(PlayerId(2)))));
+    }
+
+    #[test]
+    fn test_misere_line_loses() {
+        let rules = LineRules { misere: true };
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(2, 2, PlayerId(1));
+        match rules.game_state(&grid) {
+            GameState::Lose(PlayerId(1)) => {},
+            _ => panic!("Completing a line in a misère game should lose"),
+        }
+    }
+
+    #[test]
+    fn test_wild_line_belongs_to_last_mover() {
+        let rules = WildRules::default();
+        let mut grid = Grid::new(3, 3, 3);
+        assert_eq!(18, rules.legal_moves(&grid, PlayerId(1)).len());
+        grid.set_symbol_by_index(0, PlayerId(1), PlayerId(1));
+        grid.set_symbol_by_index(1, PlayerId(1), PlayerId(1));
+        assert!(rules.apply_move(&mut grid, PlayerId(2), &Placement::new(2, CellState::Set(PlayerId(1)))));
+        match rules.game_state(&grid) {
+            GameState::Win(PlayerId(2)) => {},
+            _ => panic!("The player completing a line in a wild game should win"),
+        }
+    }
+
+    #[test]
+    fn test_numerical_numbers_are_used_once() {
+        let rules = NumericalRules::default();
+        let mut grid = Grid::<NumberCell>::create(&[3, 3], 3, Topology::Bounded);
+        assert!(!rules.apply_move(&mut grid, PlayerId(1), &Placement::new(0, NumberCell::Set(4))));
+        assert!(rules.apply_move(&mut grid, PlayerId(1), &Placement::new(0, NumberCell::Set(5))));
+        assert!(!rules.apply_move(&mut grid, PlayerId(1), &Placement::new(1, NumberCell::Set(5))));
+        assert_eq!(8 * 4, rules.legal_moves(&grid, PlayerId(1)).len());
+        assert_eq!("0 1 6", rules.describe_move(&grid, &Placement::new(1, NumberCell::Set(6))));
+    }
+}
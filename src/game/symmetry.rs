@@ -0,0 +1,83 @@
+use super::grid_observer::coordinates_of;
+
+/// Every rotation and reflection of a grid with these dimensions, as a mapping from each index
+/// to the index it is moved to. The identity comes first.
+/// Square boards have 8 symmetries, rectangular ones 4 and cubes 48.
+pub fn symmetries(dimensions: &[usize]) -> Vec<Box<[usize]>> {
+    let cell_count = dimensions.iter().product();
+    let mut symmetries: Vec<Box<[usize]>> = Vec::new();
+    for permutation in axis_permutations(dimensions) {
+        for reflected in 0 .. 1usize << dimensions.len() {
+            let mapping: Box<[usize]> = (0 .. cell_count)
+                .map(|index| transform(dimensions, index, &permutation, reflected))
+                .collect();
+            // axes of size 1 look the same reflected or swapped, which gives duplicates
+            if !symmetries.contains(&mapping) {
+                symmetries.push(mapping);
+            }
+        }
+    }
+    symmetries
+}
+
+// Every order of the axes that only swaps axes of the same size, starting with the identity.
+fn axis_permutations(dimensions: &[usize]) -> Vec<Vec<usize>> {
+    let mut permutations = Vec::new();
+    let mut permutation = Vec::new();
+    add_permutations(dimensions, &mut permutation, &mut permutations);
+    permutations
+}
+
+fn add_permutations(dimensions: &[usize], permutation: &mut Vec<usize>, permutations: &mut Vec<Vec<usize>>) {
+    if permutation.len() == dimensions.len() {
+        permutations.push(permutation.clone());
+        return;
+    }
+    let axis = permutation.len();
+    for source in 0 .. dimensions.len() {
+        if dimensions[source] == dimensions[axis] && !permutation.contains(&source) {
+            permutation.push(source);
+            add_permutations(dimensions, permutation, permutations);
+            permutation.pop();
+        }
+    }
+}
+
+// Axis `axis` of the result takes the coordinate of axis `permutation[axis]`, mirrored if bit
+// `axis` of `reflected` is set.
+fn transform(dimensions: &[usize], index: usize, permutation: &[usize], reflected: usize) -> usize {
+    let coordinates = coordinates_of(dimensions, index);
+    let mut result = 0;
+    for (axis, &size) in dimensions.iter().enumerate() {
+        let mut coordinate = coordinates[permutation[axis]];
+        if reflected & (1 << axis) != 0 {
+            coordinate = size - 1 - coordinate;
+        }
+        result = result * size + coordinate;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_symmetry_counts() {
+        assert_eq!(8, symmetries(&[3, 3]).len());
+        assert_eq!(4, symmetries(&[3, 4]).len());
+        assert_eq!(2, symmetries(&[1, 5]).len());
+        assert_eq!(48, symmetries(&[4, 4, 4]).len());
+    }
+
+    #[test]
+    fn test_identity_comes_first() {
+        let symmetries = symmetries(&[3, 3]);
+        assert_eq!(&[0, 1, 2, 3, 4, 5, 6, 7, 8][..], &*symmetries[0]);
+        for symmetry in &symmetries {
+            // the center stays where it is, and corners stay corners
+            assert_eq!(4, symmetry[4]);
+            assert!([0, 2, 6, 8].contains(&symmetry[0]));
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod bench;
+pub mod game;
+pub mod player;
+pub mod random;
@@ -1,44 +1,415 @@
-use ::game::grid::Grid;
-use ::game::{PlayerId,GameState::*};
-use ::game::grid_observer::check_winner;
-use ::player::terminal::TerminalPlayer;
-use ::player::ki::KiPlayer;
-use ::player::Player;
+extern crate tic_tac_toe;
 
-mod game;
-mod player;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+use tic_tac_toe::game::grid::Grid;
+use tic_tac_toe::game::position;
+use tic_tac_toe::game::{PlayerId, GameState::*};
+use tic_tac_toe::game::rules::{Rules, LineRules, WildRules, NumericalRules};
+use tic_tac_toe::player::terminal::TerminalPlayer;
+use tic_tac_toe::player::ki::{self, KiPlayer, GameEvaluation, evaluate_game};
+use tic_tac_toe::player::mcts::{MctsPlayer, Budget};
+use tic_tac_toe::player::book::Book;
+use tic_tac_toe::player::solver::Database;
+use tic_tac_toe::player::review;
+use tic_tac_toe::player::selfplay::{self, CSV_HEADER};
+use tic_tac_toe::player::learning::{self, LearningPlayer, Opponent, QTable};
+use tic_tac_toe::player::evaluation::{self, Evaluator, LinearModel};
+use tic_tac_toe::player::puzzle::{self, Puzzle};
+use tic_tac_toe::random::Random;
+use tic_tac_toe::bench::{self, Baseline, Settings, REGRESSION_THRESHOLD};
+use tic_tac_toe::player::Player;
+use tic_tac_toe::game::clock::TimeControl;
+use options::{Ai, Command, Options, TrainingOpponent, USAGE};
 
-const ROWS: usize = 3;
-const COLUMNS: usize = 3;
-const TO_WIN: u32 = 3;
-const PLAYER_COUNT: usize = 2;
+mod options;
 
 fn main() {
-    let term_player_1 =  TerminalPlayer::new(1);
-    let term_player_2 =  KiPlayer::new(2);
-    let players: Vec<&Player> = vec![&term_player_1, &term_player_2];
-    let mut cur_id: usize = 0;
-    let mut grid = Grid::new(ROWS, COLUMNS, TO_WIN);
-
-    loop {
-        match check_winner(&grid) {
-            Win(PlayerId(id)) => {
-                println!("Congratulations, Player {}. You Win!", id);
-                return;
-            },
-            Mid => {
-                players[cur_id].make_turn(&mut grid);
-                if cur_id >= (PLAYER_COUNT - 1) {
-                    cur_id = 0;
-                } else {
-                    cur_id += 1;
-                }
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(1);
+        }
+    };
+
+    if options.numerical {
+        run(&NumericalRules { misere: options.misere }, &options);
+    } else if options.wild {
+        run(&WildRules { misere: options.misere }, &options);
+    } else {
+        run(&LineRules { misere: options.misere }, &options);
+    }
+}
+
+fn run<R: Rules + 'static>(rules: &R, options: &Options) {
+    match options.command {
+        Command::Play => play(rules, options),
+        Command::GenerateBook(ref path) => generate_book(rules, options, path),
+        Command::Solve(ref path) => solve(rules, options, path),
+        Command::Puzzles(ref path) => play_puzzles(rules, options, path),
+        Command::GeneratePuzzles(ref path) => generate_puzzles(rules, options, path),
+        Command::SelfPlay(ref path) => self_play(rules, options, path),
+        Command::Train(ref path) => train(rules, options, path),
+        Command::Fit(ref dataset, ref path) => fit(rules, options, dataset, path),
+        Command::Bench => run_benchmarks(options),
+    }
+}
+
+// searching to the end is hopeless on large boards, so without a limit every move gets this long
+const SELFPLAY_MOVE_TIME: Duration = Duration::from_millis(100);
+
+fn self_play<R: Rules>(rules: &R, options: &Options, path: &str) {
+    let grid = Grid::create(&options.dimensions, options.to_win, options.topology);
+    let time_control = match options.ai_time {
+        TimeControl::Unlimited => TimeControl::PerMove(SELFPLAY_MOVE_TIME),
+        time_control => time_control,
+    };
+    let fail = |error: std::io::Error| -> ! {
+        eprintln!("Couldn't write the dataset to {}: {}", path, error);
+        process::exit(1);
+    };
+    let mut writer = BufWriter::new(File::create(path).unwrap_or_else(|error| fail(error)));
+    writeln!(writer, "{}", CSV_HEADER).unwrap_or_else(|error| fail(error));
+
+    let mut random = Random::from_time();
+    let mut positions = 0;
+    // the games won by player 1, won by player 2 and drawn
+    let mut results = [0; 3];
+    for game in 1 ..= options.games {
+        let (samples, result) = selfplay::play_game(rules, &grid, time_control, options.opening, &mut random);
+        for sample in &samples {
+            writeln!(writer, "{}", sample.csv_line()).unwrap_or_else(|error| fail(error));
+        }
+        positions += samples.len();
+        match result {
+            GameEvaluation::Win => results[0] += 1,
+            GameEvaluation::Lose => results[1] += 1,
+            GameEvaluation::Draw => results[2] += 1,
+        }
+        if game % 100 == 0 {
+            println!("Played {} of {} games", game, options.games);
+        }
+    }
+    writer.flush().unwrap_or_else(|error| fail(error));
+    println!("Player 1 won {}, Player 2 won {} and {} games were drawn.", results[0], results[1], results[2]);
+    println!("Saved {} positions to {}", positions, path);
+}
+
+fn train<R: Rules>(rules: &R, options: &Options, path: &str) {
+    let grid = Grid::create(&options.dimensions, options.to_win, options.topology);
+    // training goes on from an earlier table
+    let mut table = if Path::new(path).exists() {
+        load_table(rules, &grid, path)
+    } else {
+        QTable::new(rules, &grid)
+    };
+    let opponent = match options.opponent {
+        TrainingOpponent::Minimax => Opponent::Minimax(options.ai_time),
+        TrainingOpponent::Itself => Opponent::Itself,
+    };
+    let block = (options.episodes / 10).max(1);
+    let reports = learning::train(rules, &grid, &mut table, opponent, options.episodes, block, &mut Random::from_time());
+    let mut start = 0;
+    for report in reports {
+        let games = f64::from(report.episodes - start) / 100.0;
+        println!("Games {:>6} to {:>6}: {:5.1}% won, {:5.1}% drawn, {:5.1}% lost", start + 1, report.episodes,
+                 f64::from(report.wins) / games, f64::from(report.draws) / games, f64::from(report.losses) / games);
+        start = report.episodes;
+    }
+    if let Err(error) = table.save(path) {
+        eprintln!("Couldn't save the table to {}: {}", path, error);
+        process::exit(1);
+    }
+    println!("Saved {} positions to {}", table.len(), path);
+}
+
+fn load_table<R: Rules>(rules: &R, grid: &Grid<R::Cell>, path: &str) -> QTable {
+    let table = QTable::load(path).unwrap_or_else(|error| {
+        eprintln!("Couldn't load the table {}: {}", path, error);
+        process::exit(1);
+    });
+    if !table.fits(rules, grid) {
+        eprintln!("The table {} was made for different rules or another board", path);
+        process::exit(1);
+    }
+    table
+}
+
+fn fit<R: Rules>(rules: &R, options: &Options, dataset: &str, path: &str) {
+    let csv = fs::read_to_string(dataset).unwrap_or_else(|error| {
+        eprintln!("Couldn't read the dataset {}: {}", dataset, error);
+        process::exit(1);
+    });
+    let samples = evaluation::training_data(rules, &csv, options.to_win, options.topology).unwrap_or_else(|error| {
+        eprintln!("Invalid dataset {}: {}", dataset, error);
+        process::exit(1);
+    });
+    let model = LinearModel::fit(&samples);
+    let error: f64 = samples.iter().map(|&(ref lines, outcome)| (model.evaluate(lines) - outcome).powi(2)).sum();
+    if let Err(error) = model.save(path) {
+        eprintln!("Couldn't save the model to {}: {}", path, error);
+        process::exit(1);
+    }
+    println!("Fitted {} positions with a mean squared error of {:.4}", samples.len(),
+             error / samples.len().max(1) as f64);
+    println!("Saved the model to {}", path);
+}
+
+fn run_benchmarks(options: &Options) {
+    let baseline = options.baseline.as_ref().map(|path| Baseline::load(path).unwrap_or_else(|error| {
+        eprintln!("Couldn't load the baseline {}: {}", path, error);
+        process::exit(1);
+    }));
+    let mut regressions = 0;
+    let measurements = bench::run_suite(&Settings::default(), &options.filter, |measurement| {
+        let change = match baseline.as_ref().and_then(|baseline| baseline.change(measurement)) {
+            Some(change) if change > REGRESSION_THRESHOLD => {
+                regressions += 1;
+                format!("{:+.1}%, regressed", change * 100.0)
             },
-            Draw => {
-                println!("Draw! You are equally good!");
-                return;
+            Some(change) => format!("{:+.1}%", change * 100.0),
+            None => String::new(),
+        };
+        println!("{:<36} {:>12?} [{:?} .. {:?}] {}", measurement.name, measurement.median,
+                 measurement.min, measurement.max, change);
+    });
+    if let Some(ref path) = options.save_baseline {
+        if let Err(error) = Baseline::new(&measurements).save(path) {
+            eprintln!("Couldn't save the baseline to {}: {}", path, error);
+            process::exit(1);
+        }
+        println!("Saved the times of {} benchmarks to {}", measurements.len(), path);
+    }
+    if regressions > 0 {
+        eprintln!("{} benchmarks got more than {}% slower", regressions, REGRESSION_THRESHOLD * 100.0);
+        process::exit(1);
+    }
+}
+
+fn solve<R: Rules>(rules: &R, options: &Options, path: &str) {
+    let grid = Grid::create(&options.dimensions, options.to_win, options.topology);
+    let database = Database::solve(rules, &grid);
+    let value = database.value(rules, &grid, PlayerId(1)).expect("The start of the game wasn't solved.");
+    match value.evaluation {
+        GameEvaluation::Win => println!("With perfect play Player 1 wins in {} moves.", value.moves),
+        GameEvaluation::Lose => println!("With perfect play Player 2 wins in {} moves.", value.moves),
+        GameEvaluation::Draw => println!("With perfect play the game is a draw."),
+    }
+    if let Err(error) = database.save(path) {
+        eprintln!("Couldn't save the database to {}: {}", path, error);
+        process::exit(1);
+    }
+    println!("Saved {} positions to {}", database.len(), path);
+}
+
+fn load_database<R: Rules>(rules: &R, grid: &Grid<R::Cell>, path: &str) -> Database {
+    let database = Database::load(path).unwrap_or_else(|error| {
+        eprintln!("Couldn't load the database {}: {}", path, error);
+        process::exit(1);
+    });
+    if !database.fits(rules, grid) {
+        eprintln!("The database {} was made for different rules or another board", path);
+        process::exit(1);
+    }
+    database
+}
+
+fn generate_book<R: Rules>(rules: &R, options: &Options, path: &str) {
+    let grid = Grid::create(&options.dimensions, options.to_win, options.topology);
+    let book = Book::generate(rules, &grid, options.plies, options.ai_time);
+    if let Err(error) = book.save(path) {
+        eprintln!("Couldn't save the book to {}: {}", path, error);
+        process::exit(1);
+    }
+    println!("Saved {} positions to {}", book.len(), path);
+}
+
+fn load_book<R: Rules>(rules: &R, grid: &Grid<R::Cell>, path: &str) -> Book {
+    let book = Book::load(path).unwrap_or_else(|error| {
+        eprintln!("Couldn't load the book {}: {}", path, error);
+        process::exit(1);
+    });
+    if !book.fits(rules, grid) {
+        eprintln!("The book {} was made for different rules or another board", path);
+        process::exit(1);
+    }
+    book
+}
+
+fn generate_puzzles<R: Rules>(rules: &R, options: &Options, path: &str) {
+    let grid = Grid::create(&options.dimensions, options.to_win, options.topology);
+    let puzzles = puzzle::generate(rules, &grid, options.count, options.win_in, &mut Random::from_time());
+    if puzzles.len() < options.count {
+        println!("Only found {} puzzles with a win in {} moves.", puzzles.len(), options.win_in);
+    }
+    if let Err(error) = puzzle::save(path, &puzzles) {
+        eprintln!("Couldn't save the puzzles to {}: {}", path, error);
+        process::exit(1);
+    }
+    println!("Saved {} puzzles to {}", puzzles.len(), path);
+}
+
+fn play_puzzles<R: Rules>(rules: &R, options: &Options, path: &str) {
+    let puzzles = puzzle::load(path).unwrap_or_else(|error| {
+        eprintln!("Couldn't load the puzzles {}: {}", path, error);
+        process::exit(1);
+    });
+    // the welcome is printed once for every side the human plays
+    let mut humans: Vec<Option<TerminalPlayer>> = vec![None, None];
+    let mut solved = 0;
+    for (number, puzzle) in puzzles.iter().enumerate() {
+        let (grid, player) = match position::setup(rules, &puzzle.position, options.to_win, options.topology) {
+            Ok(setup) => setup,
+            Err(error) => {
+                eprintln!("Skipping the invalid puzzle '{}': {}", puzzle.position, error);
+                continue;
+            }
+        };
+        let PlayerId(id) = player;
+        let human = humans[id as usize - 1].get_or_insert_with(|| TerminalPlayer::new(id));
+        println!("\nPuzzle {} of {}: Player {} to move, {}.", number + 1, puzzles.len(), id, puzzle);
+        if play_puzzle(rules, &grid, player, puzzle, human) {
+            println!("Solved!");
+            solved += 1;
+        } else {
+            let best = &ki::analyze(rules, &grid, player, Some(ki::ANALYSIS_TIME))[0];
+            let solution: Vec<_> = best.variation.iter().map(|mov| rules.describe_move(&grid, mov)).collect();
+            println!("Not solved. The solution was {}: {}", best.describe_value(), solution.join(", "));
+        }
+    }
+    println!("\nYou solved {} of {} puzzles.", solved, puzzles.len());
+}
+
+// Whether the human playing `player` wins against the computer within the moves of the puzzle.
+fn play_puzzle<R: Rules>(rules: &R, start: &Grid<R::Cell>, player: PlayerId, puzzle: &Puzzle,
+                         human: &TerminalPlayer) -> bool {
+    let PlayerId(defender) = rules.next_player(player);
+    let ki = KiPlayer::new(defender);
+    let mut grid = start.clone();
+    let mut current_player = player;
+    for _ in 0 .. puzzle.moves {
+        match rules.game_state(&grid) {
+            Mid => {},
+            _ => break,
+        }
+        if current_player == player {
+            human.make_turn(rules, &mut grid);
+        } else {
+            ki.make_turn(rules, &mut grid);
+        }
+        current_player = rules.next_player(current_player);
+    }
+    grid.pretty_print();
+    evaluate_game(rules, &grid, player) == Some(GameEvaluation::Win)
+}
+
+fn play<R: Rules + 'static>(rules: &R, options: &Options) {
+    let (mut grid, mut current_player) = match options.position {
+        Some(ref text) => position::setup(rules, text, options.to_win, options.topology).unwrap_or_else(|error| {
+            eprintln!("Invalid position '{}': {}", text, error);
+            process::exit(1);
+        }),
+        None => (Grid::create(&options.dimensions, options.to_win, options.topology), PlayerId(1)),
+    };
+    // without its own limit the computer plays by the game clock, so it doesn't lose on time
+    let ai_time = match options.ai_time {
+        TimeControl::Unlimited => options.clock,
+        ai_time => ai_time,
+    };
+    let term_player_2: Box<dyn Player<R>> = match options.ai {
+        Ai::Minimax => {
+            let mut ki = KiPlayer::with_time_control(2, ai_time);
+            if let Some(ref path) = options.book {
+                ki = ki.with_book(load_book(rules, &grid, path));
             }
+            if let Some(ref path) = options.database {
+                ki = ki.with_database(load_database(rules, &grid, path));
+            }
+            if let Some(distance) = options.candidates {
+                ki = ki.with_candidate_distance(distance);
+            }
+            if let Some(ref path) = options.model {
+                ki = ki.with_evaluator(LinearModel::load(path).unwrap_or_else(|error| {
+                    eprintln!("Couldn't load the model {}: {}", path, error);
+                    process::exit(1);
+                }));
+            }
+            Box::new(ki)
+        },
+        Ai::Mcts => {
+            let budget = match ai_time {
+                TimeControl::Unlimited => Budget::Iterations(options.iterations),
+                TimeControl::PerMove(limit) => Budget::Time(limit),
+                TimeControl::Clock(clock) => Budget::Clock(clock),
+            };
+            Box::new(MctsPlayer::new(2, budget))
+        },
+        Ai::Learning => {
+            let table = match options.table {
+                Some(ref path) => load_table(rules, &grid, path),
+                None => QTable::new(rules, &grid),
+            };
+            Box::new(LearningPlayer::new(2, table))
+        },
+    };
+    let term_player_1 =  TerminalPlayer::new(1);
+    let players: [&dyn Player<R>; 2] = [&term_player_1, &*term_player_2];
+    let mut clocks = vec![options.clock; players.len()];
+    let start = grid.clone();
+    let mut history = Vec::new();
+
+    let result = loop {
+        match rules.game_state(&grid) {
+            Mid => {},
+            state => break state,
+        }
+        let PlayerId(id) = current_player;
+        let player = players[id as usize - 1];
+        player.update_clocks(&clocks);
+        let before = grid.clone();
+        let start = Instant::now();
+        player.make_turn(rules, &mut grid);
+        history.extend(review::find_move(rules, &before, &grid, current_player).map(|mov| (current_player, mov)));
+        if !clocks[id as usize - 1].spend(start.elapsed()) {
+            break LossOnTime(current_player);
         }
+        current_player = rules.next_player(current_player);
+    };
+
+    match result {
+        Win(PlayerId(id)) => println!("Congratulations, Player {}. You Win!", id),
+        Lose(PlayerId(id)) => println!("Player {} completed a line and loses. Everyone else wins!", id),
+        LossOnTime(PlayerId(id)) => println!("Player {} ran out of time and loses. Everyone else wins!", id),
+        Draw => println!("Draw! You are equally good!"),
+        Mid => unreachable!(),
+    }
+    if options.review {
+        print_review(rules, &start, &history);
     }
 }
 
+fn print_review<R: Rules>(rules: &R, start: &Grid<R::Cell>, history: &[(PlayerId, R::Move)]) {
+    let reviews = review::review(rules, start, history, Some(ki::ANALYSIS_TIME));
+    let mut grid = start.clone();
+    println!("\nReview of the game:");
+    for (review, &(player, ref mov)) in reviews.iter().zip(history) {
+        rules.apply_move(&mut grid, player, mov);
+        println!();
+        grid.pretty_print();
+        println!("{}", review.describe(rules, start));
+    }
+
+    let mistakes: Vec<_> = reviews.iter().filter(|review| review.is_mistake()).collect();
+    println!("\nSummary:");
+    if mistakes.is_empty() {
+        println!("No move changed the outcome of the game.");
+    }
+    for mistake in mistakes {
+        println!("{}", mistake.describe(rules, start));
+    }
+}
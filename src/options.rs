@@ -0,0 +1,430 @@
+use std::str::FromStr;
+use std::time::Duration;
+use tic_tac_toe::game::Topology;
+use tic_tac_toe::game::clock::{Clock, TimeControl};
+
+const ROWS: usize = 3;
+const COLUMNS: usize = 3;
+const TO_WIN: u32 = 3;
+const ITERATIONS: u32 = 10_000;
+const BOOK_PLIES: u32 = 4;
+const PUZZLE_COUNT: usize = 10;
+const PUZZLE_MOVES: u32 = 3;
+const SELFPLAY_GAMES: u32 = 1000;
+const OPENING_MOVES: u32 = 2;
+const EPISODES: u32 = 10_000;
+
+pub const USAGE: &str = "\
+Usage: tic_tac_toe [COMMAND] [OPTIONS]
+
+Commands:
+    generate-book FILE  search the openings of the board and save them as a book for --book
+    solve FILE          solve every position of a small board, print who wins with perfect play
+                        and save the results as a database for --database
+    puzzles FILE        solve the puzzles of the file against the computer, e.g. \"xx./oo./... x 1\"
+                        for a position where x wins in 1 move
+    generate-puzzles FILE
+                        find puzzles in games the computer plays against itself and save them
+    selfplay FILE       let the computer play against itself and save every position with its
+                        evaluation and the outcome of the game as CSV, e.g. for training
+    fit DATASET MODEL   fit the weights of an evaluation to a dataset made by selfplay and save
+                        them as a model for --model
+    train FILE          let a learning player play --episodes games, print how its results
+                        improve and save what it learned for --table, continuing from FILE
+    bench               time the game engine and the computer opponents, build with --release
+
+Options:
+    --size SIZE      size of the board, e.g. 3x3 or 4x4x4 for Qubic (default 3x3)
+    --to-win N       number of cells in a row needed to win (default 3)
+    --wrap           lines wrap around the edges of the board
+    --misere         completing a line loses instead of winning
+    --wild           players may place either symbol, completing any line wins
+    --numerical      players place odd and even numbers, a line with the magic sum wins
+    --ai AI          the computer opponent, minimax (perfect but slow), mcts or learning
+                     (default minimax)
+    --iterations N   number of iterations the mcts opponent searches per move (default 10000)
+    --ai-time TIME   time the computer may think per move, e.g. 500ms, 2s or 1m
+    --ai-clock CLOCK total time of the computer for the game plus increment per move, e.g. 5m+2s
+    --clock CLOCK    a game clock for every player, e.g. 5m+2s, running out of time loses
+    --move-time TIME a time limit per move for every player, exceeding it loses
+    --position POS   start from a position like x.o/.x./..o, rows separated by / and layers by |,
+                     optionally followed by the player to move, e.g. \"x.o/.x./..o o\"
+    --review         replay the game afterwards and point out the moves that changed the outcome
+    --book FILE      an opening book the minimax opponent plays from instantly
+    --plies N        number of moves generate-book covers from the start of the game (default 4)
+    --database FILE  a database made by solve the minimax opponent plays perfectly from
+    --table FILE     what the learning opponent learned, made by train
+    --episodes N     number of games train plays (default 10000)
+    --opponent OPP   whom train plays against, minimax or self (default minimax)
+    --model FILE     a model made by fit the minimax opponent judges positions by when it
+                     runs out of time, instead of its own rules of thumb
+    --candidates N   the minimax opponent only searches moves at most N cells from a stone, 0 for
+                     all moves (default 2 on boards over 64 cells, otherwise all moves)
+    --count N        number of puzzles generate-puzzles looks for (default 10)
+    --win-in N       number of moves to the win in generated puzzles, counting both players (default 3)
+    --games N        number of games selfplay plays (default 1000)
+    --opening N      number of random moves selfplay starts every game with (default 2)
+    --filter TEXT    only run the benchmarks with TEXT in their name
+    --save-baseline FILE
+                     save the benchmark times, to compare later runs with
+    --baseline FILE  compare the benchmark times with a saved baseline, failing on regressions";
+
+/// What the program does.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Command {
+    Play,
+    // search the openings and save them as a book at the path
+    GenerateBook(String),
+    // solve the board and save the database at the path
+    Solve(String),
+    // play the puzzles of the file at the path
+    Puzzles(String),
+    // find puzzles and save them at the path
+    GeneratePuzzles(String),
+    // play against itself and save the positions at the path
+    SelfPlay(String),
+    // train a learning player and save its table at the path
+    Train(String),
+    // fit an evaluation to the dataset at the first path and save it at the second
+    Fit(String, String),
+    Bench,
+}
+
+/// The algorithm of the computer opponent.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Ai {
+    Minimax,
+    Mcts,
+    Learning,
+}
+
+/// Whom the learning player plays while training.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum TrainingOpponent {
+    Minimax,
+    Itself,
+}
+
+pub struct Options {
+    pub command: Command,
+    pub dimensions: Vec<usize>,
+    pub to_win: u32,
+    pub topology: Topology,
+    pub misere: bool,
+    pub wild: bool,
+    pub numerical: bool,
+    pub ai: Ai,
+    pub iterations: u32,
+    pub ai_time: TimeControl,
+    pub clock: TimeControl,
+    pub position: Option<String>,
+    pub review: bool,
+    pub book: Option<String>,
+    pub database: Option<String>,
+    pub model: Option<String>,
+    pub candidates: Option<u32>,
+    pub table: Option<String>,
+    pub episodes: u32,
+    pub opponent: TrainingOpponent,
+    pub plies: u32,
+    pub count: usize,
+    pub win_in: u32,
+    pub games: u32,
+    pub opening: u32,
+    pub filter: String,
+    pub baseline: Option<String>,
+    pub save_baseline: Option<String>,
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            command: Command::Play,
+            dimensions: vec![ROWS, COLUMNS],
+            to_win: TO_WIN,
+            topology: Topology::Bounded,
+            misere: false,
+            wild: false,
+            numerical: false,
+            ai: Ai::Minimax,
+            iterations: ITERATIONS,
+            ai_time: TimeControl::Unlimited,
+            clock: TimeControl::Unlimited,
+            position: None,
+            review: false,
+            book: None,
+            database: None,
+            model: None,
+            candidates: None,
+            table: None,
+            episodes: EPISODES,
+            opponent: TrainingOpponent::Minimax,
+            plies: BOOK_PLIES,
+            count: PUZZLE_COUNT,
+            win_in: PUZZLE_MOVES,
+            games: SELFPLAY_GAMES,
+            opening: OPENING_MOVES,
+            filter: String::new(),
+            baseline: None,
+            save_baseline: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--size" => options.dimensions = parse_size(&value_of(&arg, args.next())?)?,
+                "--to-win" => options.to_win = match parse_number(&arg, &value_of(&arg, args.next())?)? {
+                    0 => return Err("invalid value '0' for --to-win".to_string()),
+                    to_win => to_win,
+                },
+                "--wrap" => options.topology = Topology::Toroidal,
+                "--misere" => options.misere = true,
+                "--wild" => options.wild = true,
+                "--numerical" => options.numerical = true,
+                "--ai" => options.ai = match value_of(&arg, args.next())?.as_str() {
+                    "minimax" => Ai::Minimax,
+                    "mcts" => Ai::Mcts,
+                    "learning" => Ai::Learning,
+                    ai => return Err(format!("invalid value '{}' for --ai", ai)),
+                },
+                "--iterations" => options.iterations = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--ai-time" => options.ai_time =
+                    TimeControl::PerMove(parse_duration(&arg, &value_of(&arg, args.next())?)?),
+                "--ai-clock" => options.ai_time =
+                    TimeControl::Clock(parse_clock(&arg, &value_of(&arg, args.next())?)?),
+                "--clock" => options.clock =
+                    TimeControl::Clock(parse_clock(&arg, &value_of(&arg, args.next())?)?),
+                "--move-time" => options.clock =
+                    TimeControl::PerMove(parse_duration(&arg, &value_of(&arg, args.next())?)?),
+                "--position" => options.position = Some(value_of(&arg, args.next())?),
+                "--review" => options.review = true,
+                "--book" => options.book = Some(value_of(&arg, args.next())?),
+                "--database" => options.database = Some(value_of(&arg, args.next())?),
+                "--plies" => options.plies = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--count" => options.count = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--win-in" => options.win_in = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--games" => options.games = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--opening" => options.opening = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--table" => options.table = Some(value_of(&arg, args.next())?),
+                "--episodes" => options.episodes = parse_number(&arg, &value_of(&arg, args.next())?)?,
+                "--opponent" => options.opponent = match value_of(&arg, args.next())?.as_str() {
+                    "minimax" => TrainingOpponent::Minimax,
+                    "self" => TrainingOpponent::Itself,
+                    opponent => return Err(format!("invalid value '{}' for --opponent", opponent)),
+                },
+                "--model" => options.model = Some(value_of(&arg, args.next())?),
+                "--candidates" => options.candidates = Some(parse_number(&arg, &value_of(&arg, args.next())?)?),
+                "--filter" => options.filter = value_of(&arg, args.next())?,
+                "--baseline" => options.baseline = Some(value_of(&arg, args.next())?),
+                "--save-baseline" => options.save_baseline = Some(value_of(&arg, args.next())?),
+                "generate-book" => options.command = Command::GenerateBook(value_of(&arg, args.next())?),
+                "solve" => options.command = Command::Solve(value_of(&arg, args.next())?),
+                "puzzles" => options.command = Command::Puzzles(value_of(&arg, args.next())?),
+                "generate-puzzles" => options.command = Command::GeneratePuzzles(value_of(&arg, args.next())?),
+                "selfplay" => options.command = Command::SelfPlay(value_of(&arg, args.next())?),
+                "train" => options.command = Command::Train(value_of(&arg, args.next())?),
+                "fit" => {
+                    let dataset = value_of(&arg, args.next())?;
+                    options.command = Command::Fit(dataset, value_of(&arg, args.next())?);
+                },
+                "bench" => options.command = Command::Bench,
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+        if options.wild && options.numerical {
+            return Err("--wild and --numerical can't be combined".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn value_of(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+fn parse_duration(flag: &str, value: &str) -> Result<Duration, String> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = parse_number(flag, number)?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        _ => Err(format!("invalid value '{}' for {}, the unit must be ms, s or m", value, flag)),
+    }
+}
+
+fn parse_clock(flag: &str, value: &str) -> Result<Clock, String> {
+    let mut parts = value.splitn(2, '+');
+    let total = parse_duration(flag, parts.next().unwrap_or(""))?;
+    let increment = match parts.next() {
+        Some(increment) => parse_duration(flag, increment)?,
+        None => Duration::from_secs(0),
+    };
+    Ok(Clock::new(total, increment))
+}
+
+fn parse_size(value: &str) -> Result<Vec<usize>, String> {
+    let dimensions = value.split('x')
+        .map(|size| parse_number("--size", size))
+        .collect::<Result<Vec<usize>, String>>()?;
+    if dimensions.contains(&0) {
+        return Err(format!("invalid value '{}' for --size", value));
+    }
+    Ok(dimensions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(vec![3, 3], options.dimensions);
+        assert_eq!(3, options.to_win);
+        assert_eq!(Topology::Bounded, options.topology);
+        assert!(!options.misere);
+        assert!(!options.wild);
+        assert!(!options.numerical);
+        assert_eq!(Ai::Minimax, options.ai);
+        assert_eq!(Command::Play, options.command);
+        assert_eq!(None, options.book);
+        assert!(!options.review);
+        assert_eq!(None, options.position);
+    }
+
+    #[test]
+    fn test_book() {
+        let options = parse(&["generate-book", "3x3.book", "--plies", "6"]).unwrap();
+        assert_eq!(Command::GenerateBook("3x3.book".to_string()), options.command);
+        assert_eq!(6, options.plies);
+        let options = parse(&["--book", "3x3.book"]).unwrap();
+        assert_eq!(Some("3x3.book".to_string()), options.book);
+        assert!(parse(&["generate-book"]).is_err());
+    }
+
+    #[test]
+    fn test_solve() {
+        let options = parse(&["solve", "4x4.db", "--size", "4x4"]).unwrap();
+        assert_eq!(Command::Solve("4x4.db".to_string()), options.command);
+        let options = parse(&["--database", "4x4.db"]).unwrap();
+        assert_eq!(Some("4x4.db".to_string()), options.database);
+    }
+
+    #[test]
+    fn test_puzzles() {
+        let options = parse(&["puzzles", "3x3.txt"]).unwrap();
+        assert_eq!(Command::Puzzles("3x3.txt".to_string()), options.command);
+        let options = parse(&["generate-puzzles", "3x3.txt", "--count", "5", "--win-in", "5"]).unwrap();
+        assert_eq!(Command::GeneratePuzzles("3x3.txt".to_string()), options.command);
+        assert_eq!(5, options.count);
+        assert_eq!(5, options.win_in);
+        assert!(parse(&["--win-in", "three"]).is_err());
+    }
+
+    #[test]
+    fn test_selfplay() {
+        let options = parse(&["selfplay", "games.csv", "--games", "50", "--opening", "4"]).unwrap();
+        assert_eq!(Command::SelfPlay("games.csv".to_string()), options.command);
+        assert_eq!(50, options.games);
+        assert_eq!(4, options.opening);
+        assert_eq!(1000, parse(&[]).unwrap().games);
+    }
+
+    #[test]
+    fn test_bench() {
+        let options = parse(&["bench", "--filter", "ai", "--baseline", "old.txt", "--save-baseline", "new.txt"]).unwrap();
+        assert_eq!(Command::Bench, options.command);
+        assert_eq!("ai", options.filter);
+        assert_eq!(Some("old.txt".to_string()), options.baseline);
+        assert_eq!(Some("new.txt".to_string()), options.save_baseline);
+        assert_eq!("", parse(&["bench"]).unwrap().filter);
+    }
+
+    #[test]
+    fn test_train() {
+        let options = parse(&["train", "table.bin", "--episodes", "500", "--opponent", "self"]).unwrap();
+        assert_eq!(Command::Train("table.bin".to_string()), options.command);
+        assert_eq!(500, options.episodes);
+        assert_eq!(TrainingOpponent::Itself, options.opponent);
+        assert_eq!(TrainingOpponent::Minimax, parse(&["train", "table.bin"]).unwrap().opponent);
+        assert!(parse(&["train", "table.bin", "--opponent", "human"]).is_err());
+
+        let options = parse(&["--ai", "learning", "--table", "table.bin"]).unwrap();
+        assert_eq!(Ai::Learning, options.ai);
+        assert_eq!(Some("table.bin".to_string()), options.table);
+    }
+
+    #[test]
+    fn test_fit() {
+        let options = parse(&["fit", "games.csv", "model.txt"]).unwrap();
+        assert_eq!(Command::Fit("games.csv".to_string(), "model.txt".to_string()), options.command);
+        assert!(parse(&["fit", "games.csv"]).is_err());
+        assert_eq!(Some("model.txt".to_string()), parse(&["--model", "model.txt"]).unwrap().model);
+    }
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(None, parse(&[]).unwrap().candidates);
+        assert_eq!(Some(0), parse(&["--candidates", "0"]).unwrap().candidates);
+        assert!(parse(&["--candidates", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_mcts_opponent() {
+        let options = parse(&["--ai", "mcts", "--iterations", "500"]).unwrap();
+        assert_eq!(Ai::Mcts, options.ai);
+        assert_eq!(500, options.iterations);
+        assert!(parse(&["--ai", "alphazero"]).is_err());
+    }
+
+    #[test]
+    fn test_ai_time() {
+        let options = parse(&["--ai-time", "500ms"]).unwrap();
+        assert_eq!(TimeControl::PerMove(Duration::from_millis(500)), options.ai_time);
+        let options = parse(&["--ai-clock", "5m+2s"]).unwrap();
+        let clock = Clock::new(Duration::from_secs(300), Duration::from_secs(2));
+        assert_eq!(TimeControl::Clock(clock), options.ai_time);
+        assert!(parse(&["--ai-time", "5h"]).is_err());
+        assert!(parse(&["--ai-time", "ms"]).is_err());
+    }
+
+    #[test]
+    fn test_game_clock() {
+        let options = parse(&["--clock", "10m"]).unwrap();
+        let clock = Clock::new(Duration::from_secs(600), Duration::from_secs(0));
+        assert_eq!(TimeControl::Clock(clock), options.clock);
+        let options = parse(&["--move-time", "30s"]).unwrap();
+        assert_eq!(TimeControl::PerMove(Duration::from_secs(30)), options.clock);
+    }
+
+    #[test]
+    fn test_qubic_size() {
+        let options = parse(&["--size", "4x4x4", "--to-win", "4", "--wrap", "--misere", "--wild"]).unwrap();
+        assert_eq!(vec![4, 4, 4], options.dimensions);
+        assert_eq!(4, options.to_win);
+        assert_eq!(Topology::Toroidal, options.topology);
+        assert!(options.misere);
+        assert!(options.wild);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(parse(&["--size", "3x0"]).is_err());
+        assert!(parse(&["--to-win"]).is_err());
+        assert!(parse(&["--to-win", "0"]).is_err());
+        assert!(parse(&["--colour"]).is_err());
+        assert!(parse(&["--wild", "--numerical"]).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use ::player::ki::KiPlayer;
+use ::player::storage::{self, Setup};
+use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::game::clock::TimeControl;
+use ::game::{Cell, PlayerId, GameState};
+
+const MAGIC: &[u8] = b"tttbook1";
+
+// the player to move and the codes of the cells of a canonical grid
+type Position = (u32, Box<[u32]>);
+
+/// An opening book: the best move in the positions at the start of a game, found by deep
+/// searches beforehand. Positions are stored in their canonical form, so one entry covers
+/// all rotations and reflections of a position.
+///
+/// On disk a book is the magic bytes `tttbook1`, the rules and board it was made for and then
+/// one entry per position: the player to move, one byte per cell, the index of the cell to
+/// play in the canonical grid and the cell to put there.
+pub struct Book {
+    setup: Setup,
+    // the index and code of the cell to play in each position
+    entries: HashMap<Position, (usize, u32)>,
+}
+
+impl Book {
+    /// An empty book for the rules and board of `grid`.
+    pub fn new<R: Rules>(rules: &R, grid: &Grid<R::Cell>) -> Book {
+        Book {
+            setup: Setup::new(rules, grid),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Builds a book for every position reachable from `start` in less than `plies` moves,
+    /// searching each of them with `time_control`, starting with player 1.
+    pub fn generate<R: Rules>(rules: &R, start: &Grid<R::Cell>, plies: u32, time_control: TimeControl) -> Book {
+        let mut book = Book::new(rules, start);
+        let mut positions = vec![(start.canonical(), PlayerId(1))];
+        let mut seen = HashSet::new();
+        for _ in 0 .. plies {
+            let mut next_positions = Vec::new();
+            for (grid, player) in positions {
+                match rules.game_state(&grid) {
+                    GameState::Mid => {},
+                    _ => continue,
+                }
+                let PlayerId(id) = player;
+                let mov = KiPlayer::with_time_control(id, time_control).best_move(rules, &grid);
+                let mut after = grid.clone();
+                rules.apply_move(&mut after, player, &mov);
+                let index = (0 .. grid.cell_count())
+                    .find(|&index| grid.get_cell_by_index(index) != after.get_cell_by_index(index))
+                    .expect("The move didn't place anything.");
+                book.entries.insert((id, storage::codes(&grid)), (index, after.get_cell_by_index(index).code()));
+
+                let next_player = rules.next_player(player);
+                for mov in rules.legal_moves(&grid, player) {
+                    let mut child = grid.clone();
+                    rules.apply_move(&mut child, player, &mov);
+                    let child = child.canonical();
+                    let PlayerId(next_id) = next_player;
+                    if seen.insert((next_id, child.clone())) {
+                        next_positions.push((child, next_player));
+                    }
+                }
+            }
+            positions = next_positions;
+        }
+        book
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether the book was made for these rules and this board.
+    pub fn fits<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) -> bool {
+        self.setup.fits(rules, grid)
+    }
+
+    /// The book move of `player` on `grid`, if the position is in the book.
+    pub fn lookup<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> Option<R::Move> {
+        if !self.fits(rules, grid) {
+            return None;
+        }
+        let canonical = grid.canonical();
+        let PlayerId(id) = player;
+        let &(canonical_index, code) = self.entries.get(&(id, storage::codes(&canonical)))?;
+        // find where the cell of the canonical grid is on the actual grid
+        let symmetry = grid.symmetries().iter().find(|symmetry| grid.transformed(symmetry) == canonical)?;
+        let index = symmetry.iter().position(|&target| target == canonical_index)?;
+        rules.legal_moves(grid, player).into_iter().find(|mov| {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, mov)
+                && *grid.get_cell_by_index(index) == R::Cell::unset()
+                && new_grid.get_cell_by_index(index).code() == code
+        })
+    }
+
+    pub fn load(path: &str) -> io::Result<Book> {
+        Book::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read_from<T: Read>(reader: &mut T) -> io::Result<Book> {
+        let setup = Setup::read_from(reader, MAGIC)?;
+        let cell_count = setup.cell_count();
+        let mut entries = HashMap::new();
+        for _ in 0 .. storage::read_u32(reader)? {
+            let player = u32::from(storage::read_u8(reader)?);
+            let cells = storage::read_codes(reader, cell_count)?;
+            let index = storage::read_u32(reader)? as usize;
+            let code = u32::from(storage::read_u8(reader)?);
+            if index >= cell_count {
+                return Err(storage::invalid_data("book move outside of the board"));
+            }
+            entries.insert((player, cells), (index, code));
+        }
+        Ok(Book {
+            setup,
+            entries,
+        })
+    }
+
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.setup.write_to(writer, MAGIC)?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        // sorted, so the same book always gives the same file
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort();
+        for (&(player, ref cells), &(index, code)) in entries {
+            writer.write_all(&[player as u8])?;
+            storage::write_codes(writer, cells)?;
+            writer.write_all(&(index as u32).to_le_bytes())?;
+            writer.write_all(&[storage::byte(code)?])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::LineRules;
+    use ::game::{CellState, PlayerId};
+
+    #[test]
+    fn test_generate_covers_symmetric_openings() {
+        let rules = LineRules::default();
+        let book = Book::generate(&rules, &Grid::new(3, 3, 3), 2, TimeControl::Unlimited);
+        // the empty board and the three different first moves
+        assert_eq!(4, book.len());
+
+        for &(row, column) in &[(0, 0), (0, 2), (2, 0), (2, 2)] {
+            let mut grid = Grid::new(3, 3, 3);
+            grid.set_cell(row, column, PlayerId(1));
+            let mov = book.lookup(&rules, &grid, PlayerId(2)).expect("An opening is missing in the book");
+            // against a corner only the center draws
+            assert_eq!(grid.index_of(&[1, 1]), mov.index);
+            assert_eq!(CellState::Set(PlayerId(2)), mov.cell);
+        }
+    }
+
+    #[test]
+    fn test_book_survives_disk_format() {
+        let rules = LineRules::default();
+        let book = Book::generate(&rules, &Grid::new(3, 3, 3), 2, TimeControl::Unlimited);
+        let mut bytes = Vec::new();
+        book.write_to(&mut bytes).unwrap();
+        let loaded = Book::read_from(&mut &bytes[..]).unwrap();
+
+        assert_eq!(book.len(), loaded.len());
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 1, PlayerId(1));
+        assert!(book.lookup(&rules, &grid, PlayerId(2)) == loaded.lookup(&rules, &grid, PlayerId(2)));
+        assert!(Book::read_from(&mut &bytes[1 ..]).is_err());
+    }
+
+    #[test]
+    fn test_book_only_fits_its_own_rules() {
+        let book = Book::generate(&LineRules::default(), &Grid::new(3, 3, 3), 1, TimeControl::Unlimited);
+        let grid = Grid::new(3, 3, 3);
+        assert!(book.lookup(&LineRules::default(), &grid, PlayerId(1)).is_some());
+        assert!(book.lookup(&LineRules { misere: true }, &grid, PlayerId(1)).is_none());
+        assert!(book.lookup(&LineRules::default(), &Grid::new(4, 4, 3), PlayerId(1)).is_none());
+    }
+}
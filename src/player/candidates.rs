@@ -0,0 +1,239 @@
+use std::rc::Rc;
+use ::game::bitboard::{BitBoard, Indices, MAX_CELLS};
+use ::game::grid::Grid;
+use ::game::{Cell, PlayerId, Topology};
+
+/// How far from the stones the moves searched on large boards may be, by default.
+pub const CANDIDATE_DISTANCE: u32 = 2;
+
+/// The moves worth searching on boards too large for all of them: the empty cells at most a
+/// distance away from a stone in every direction, or the centre on an empty board.
+#[derive(Clone)]
+pub struct Candidates {
+    // the cells within the distance of every cell
+    near: Rc<[Box<[usize]>]>,
+    // the same as bit masks, on boards a bitboard holds
+    near_masks: Rc<[u64]>,
+    center: usize,
+}
+
+impl Candidates {
+    pub fn new<C: Cell>(grid: &Grid<C>, distance: u32) -> Candidates {
+        let dimensions = grid.dimensions();
+        let near: Vec<Box<[usize]>> = (0 .. grid.cell_count()).map(|index| {
+            let coordinates = grid.coordinates(index);
+            let mut cells = vec![Vec::new()];
+            for (&coordinate, &size) in coordinates.iter().zip(dimensions.iter()) {
+                let reach = (distance as usize).min(size - 1) as isize;
+                let mut extended = Vec::new();
+                for offset in -reach ..= reach {
+                    let target = coordinate as isize + offset;
+                    let target = match grid.topology() {
+                        Topology::Bounded if target < 0 || target >= size as isize => continue,
+                        Topology::Bounded => target as usize,
+                        Topology::Toroidal => target.rem_euclid(size as isize) as usize,
+                    };
+                    for cell in &cells {
+                        let mut cell: Vec<usize> = cell.clone();
+                        cell.push(target);
+                        extended.push(cell);
+                    }
+                }
+                cells = extended;
+            }
+            let mut cells: Vec<usize> = cells.iter().map(|cell| grid.index_of(cell)).collect();
+            cells.sort();
+            cells.dedup();
+            cells.into_boxed_slice()
+        }).collect();
+        let near_masks = if grid.cell_count() <= MAX_CELLS {
+            near.iter().map(|cells| cells.iter().fold(0, |mask, &index| mask | 1u64 << index)).collect()
+        } else {
+            Vec::new()
+        };
+        let center: Vec<usize> = dimensions.iter().map(|&size| size / 2).collect();
+        Candidates {
+            near: near.into(),
+            near_masks: near_masks.into(),
+            center: grid.index_of(&center),
+        }
+    }
+
+    /// The empty cells of `grid` near a stone, in the order of their indices.
+    pub fn cells<C: Cell>(&self, grid: &Grid<C>) -> Vec<usize> {
+        let mut near = vec![false; grid.cell_count()];
+        let mut any = false;
+        for index in 0 .. grid.cell_count() {
+            if *grid.get_cell_by_index(index) != C::unset() {
+                any = true;
+                for &cell in self.near[index].iter() {
+                    near[cell] = true;
+                }
+            }
+        }
+        if !any {
+            return vec![self.center];
+        }
+        grid.empty_cells().filter(|&index| near[index]).collect()
+    }
+
+    /// The empty cells of `board` near a stone, as a bit mask.
+    pub fn mask(&self, board: &BitBoard) -> u64 {
+        let occupied = board.occupied();
+        if occupied == 0 {
+            return 1 << self.center;
+        }
+        let near = Indices(occupied).fold(0, |near, index| near | self.near_masks[index]);
+        near & board.empty_mask()
+    }
+}
+
+/// Orders `cells` by the threats they make or stop for `player`, with `owner` telling whose
+/// stone a cell is. A move that wins right away comes alone, so do the moves that stop the
+/// opponent from winning with the next move. Otherwise the cells on lines one player has more
+/// stones on come first.
+pub fn order_by_threats<C: Cell, F: Fn(&C) -> Option<PlayerId>>(grid: &Grid<C>, player: PlayerId, cells: Vec<usize>,
+                                                                owner: F) -> Vec<usize> {
+    let to_win = grid.to_win;
+    let mut scores = vec![0u64; grid.cell_count()];
+    let mut wins = Vec::new();
+    let mut blocks = Vec::new();
+    for line in grid.lines() {
+        let (mut own, mut opponent) = (0, 0);
+        for &index in line.iter() {
+            match owner(grid.get_cell_by_index(index)) {
+                Some(stone) if stone == player => own += 1,
+                Some(_) => opponent += 1,
+                None => {},
+            }
+        }
+        let empty = line.iter().filter(|&&index| *grid.get_cell_by_index(index) == C::unset());
+        if opponent == 0 && own > 0 {
+            if own + 1 == to_win {
+                wins.extend(empty);
+            } else {
+                // making threats counts a bit more than stopping them
+                empty.for_each(|&index| scores[index] += 2 << (2 * own));
+            }
+        } else if own == 0 && opponent > 0 {
+            if opponent + 1 == to_win {
+                blocks.extend(empty);
+            } else {
+                empty.for_each(|&index| scores[index] += 1 << (2 * opponent));
+            }
+        }
+    }
+    let mut forced = if wins.is_empty() { blocks } else { wins };
+    if !forced.is_empty() {
+        forced.sort();
+        forced.dedup();
+        return forced;
+    }
+    let mut cells = cells;
+    cells.sort_by_key(|&index| u64::MAX - scores[index]);
+    cells
+}
+
+/// The same as `order_by_threats` on a bitboard, for the cells of `candidates`: the cells to try
+/// first and the rest, a win or the blocks of the opponent's wins leaving no rest.
+pub fn threat_masks(board: &BitBoard, player: PlayerId, candidates: u64) -> (u64, u64) {
+    let mine = board.stones(player);
+    let empty = board.empty_mask();
+    let theirs = !empty & !mine;
+    let (mut wins, mut blocks, mut threats) = (0, 0, 0);
+    for &line in board.lines() {
+        let to_win = line.count_ones();
+        let (own, opponent) = ((line & mine).count_ones(), (line & theirs).count_ones());
+        if opponent == 0 && own + 1 == to_win {
+            wins |= line & empty;
+        } else if own == 0 && opponent + 1 == to_win {
+            blocks |= line & empty;
+        } else if (opponent == 0 && own + 2 == to_win) || (own == 0 && opponent + 2 == to_win) {
+            threats |= line & empty;
+        }
+    }
+    if wins != 0 {
+        (wins, 0)
+    } else if blocks != 0 {
+        (blocks, 0)
+    } else {
+        (candidates & threats, candidates & !threats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::{Rules, LineRules};
+
+    fn order(grid: &Grid, player: PlayerId) -> Vec<usize> {
+        let cells = Candidates::new(grid, 1).cells(grid);
+        order_by_threats(grid, player, cells, |cell| LineRules::default().owner(cell))
+    }
+
+    #[test]
+    fn test_cells_near_stones() {
+        let mut grid = Grid::new(15, 15, 5);
+        let candidates = Candidates::new(&grid, 2);
+        assert_eq!(vec![grid.index_of(&[7, 7])], candidates.cells(&grid));
+
+        grid.set_cell(0, 0, PlayerId(1));
+        assert_eq!(8, candidates.cells(&grid).len());
+        grid.set_cell(7, 7, PlayerId(2));
+        assert_eq!(8 + 24, candidates.cells(&grid).len());
+        assert!(!candidates.cells(&grid).contains(&grid.index_of(&[0, 3])));
+
+        let mut wrapping = Grid::with_topology(&[5, 5], 3, Topology::Toroidal);
+        wrapping.set_cell(0, 0, PlayerId(1));
+        let cells = Candidates::new(&wrapping, 1).cells(&wrapping);
+        assert_eq!(8, cells.len());
+        assert!(cells.contains(&wrapping.index_of(&[4, 4])));
+    }
+
+    #[test]
+    fn test_bit_masks_match_cells() {
+        let mut grid = Grid::new(8, 8, 5);
+        let rules = LineRules::default();
+        let candidates = Candidates::new(&grid, 2);
+        let board = BitBoard::from_grid(&grid, |cell| rules.owner(cell)).unwrap();
+        assert_eq!(candidates.cells(&grid), Indices(candidates.mask(&board)).collect::<Vec<_>>());
+        grid.set_cell(1, 2, PlayerId(1));
+        grid.set_cell(6, 6, PlayerId(2));
+        let board = BitBoard::from_grid(&grid, |cell| rules.owner(cell)).unwrap();
+        assert_eq!(candidates.cells(&grid), Indices(candidates.mask(&board)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_threats_come_first() {
+        let rules = LineRules::default();
+        let mut grid = Grid::new(8, 8, 4);
+        grid.set_cell(4, 3, PlayerId(1));
+        grid.set_cell(4, 4, PlayerId(1));
+        grid.set_cell(0, 0, PlayerId(2));
+        // extending the pair makes the strongest threat
+        let cells = order(&grid, PlayerId(1));
+        assert!([grid.index_of(&[4, 2]), grid.index_of(&[4, 5])].contains(&cells[0]));
+
+        grid.set_cell(4, 5, PlayerId(1));
+        // with three in a row o has to block one of the ends, and x wins at either
+        assert_eq!(vec![grid.index_of(&[4, 2]), grid.index_of(&[4, 6])], order(&grid, PlayerId(2)));
+        assert_eq!(vec![grid.index_of(&[4, 2]), grid.index_of(&[4, 6])], order(&grid, PlayerId(1)));
+
+        let board = BitBoard::from_grid(&grid, |cell| rules.owner(cell)).unwrap();
+        let all = board.empty_mask();
+        let (first, rest) = threat_masks(&board, PlayerId(2), all);
+        assert_eq!(vec![grid.index_of(&[4, 2]), grid.index_of(&[4, 6])], Indices(first).collect::<Vec<_>>());
+        assert_eq!(0, rest);
+
+        let mut grid = Grid::new(8, 8, 4);
+        grid.set_cell(3, 3, PlayerId(1));
+        grid.set_cell(3, 4, PlayerId(1));
+        let board = BitBoard::from_grid(&grid, |cell| rules.owner(cell)).unwrap();
+        let all = board.empty_mask();
+        let (first, rest) = threat_masks(&board, PlayerId(2), all);
+        assert_ne!(0, first & 1 << grid.index_of(&[3, 5]));
+        assert_eq!(all, first | rest);
+        assert_eq!(0, first & rest);
+    }
+}
@@ -0,0 +1,336 @@
+use std::fs;
+use std::io;
+use ::game::bitboard::BitBoard;
+use ::game::grid::Grid;
+use ::game::position;
+use ::game::rules::Rules;
+use ::game::{Cell, PlayerId, Topology};
+
+/// The number of stone counts evaluators tell apart, lines with more stones count as the last one.
+pub const PATTERNS: usize = 8;
+
+/// The lines each player could still complete, by the number of stones already on them:
+/// `own[k - 1]` lines hold k stones of the player to move and none of the opponent.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct LineCounts {
+    pub own: [u32; PATTERNS],
+    pub opponent: [u32; PATTERNS],
+}
+
+impl LineCounts {
+    /// Counts the lines of `grid` for `player`, with `owner` telling whose stone a cell is.
+    pub fn of_grid<C: Cell, F: Fn(&C) -> Option<PlayerId>>(grid: &Grid<C>, player: PlayerId, owner: F) -> LineCounts {
+        let mut counts = LineCounts::default();
+        for line in grid.lines() {
+            let (mut own, mut opponent) = (0, 0);
+            for &index in line.iter() {
+                match owner(grid.get_cell_by_index(index)) {
+                    Some(stone) if stone == player => own += 1,
+                    Some(_) => opponent += 1,
+                    None => {},
+                }
+            }
+            counts.add(own, opponent);
+        }
+        counts
+    }
+
+    /// Counts the lines of `board` for `player`.
+    pub fn of_board(board: &BitBoard, player: PlayerId) -> LineCounts {
+        let mine = board.stones(player);
+        let theirs = !board.empty_mask() & !mine;
+        let mut counts = LineCounts::default();
+        for &line in board.lines() {
+            counts.add((line & mine).count_ones(), (line & theirs).count_ones());
+        }
+        counts
+    }
+
+    fn add(&mut self, own: u32, opponent: u32) {
+        if opponent == 0 && own > 0 {
+            self.own[own.min(PATTERNS as u32) as usize - 1] += 1;
+        } else if own == 0 && opponent > 0 {
+            self.opponent[opponent.min(PATTERNS as u32) as usize - 1] += 1;
+        }
+    }
+}
+
+/// Estimates how good an undecided position is for the player to move when the search stops
+/// at its horizon, from -1 for lost to 1 for won.
+pub trait Evaluator {
+    fn evaluate(&self, lines: &LineCounts) -> f64;
+}
+
+/// The hand-written evaluation: every line a player could still complete counts for them,
+/// three times as much for every stone already on it.
+pub struct LineHeuristic;
+
+impl Evaluator for LineHeuristic {
+    fn evaluate(&self, lines: &LineCounts) -> f64 {
+        let mut sum = 0.0;
+        let mut weight = 1.0;
+        for (&own, &opponent) in lines.own.iter().zip(lines.opponent.iter()) {
+            sum += weight * (f64::from(own) - f64::from(opponent));
+            weight *= 3.0;
+        }
+        let sum = sum / 50.0;
+        sum / (1.0 + sum.abs())
+    }
+}
+
+/// A learned evaluation: a weight for every count of lines, added up and capped at -1 and 1.
+///
+/// In a file every weight is a line like "own 2 0.125" or "opponent 1 -0.03", plus one line
+/// "bias 0.1" for the advantage of moving. Empty lines and lines starting with '#' are skipped.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct LinearModel {
+    pub own: [f64; PATTERNS],
+    pub opponent: [f64; PATTERNS],
+    pub bias: f64,
+}
+
+impl Evaluator for LinearModel {
+    fn evaluate(&self, lines: &LineCounts) -> f64 {
+        let mut sum = self.bias;
+        for pattern in 0 .. PATTERNS {
+            sum += self.own[pattern] * f64::from(lines.own[pattern]);
+            sum += self.opponent[pattern] * f64::from(lines.opponent[pattern]);
+        }
+        sum.clamp(-1.0, 1.0)
+    }
+}
+
+impl LinearModel {
+    /// Fits the weights by least squares to positions and how the games ended for the player
+    /// to move, 1 for a win, 0 for a draw and -1 for a loss.
+    pub fn fit(samples: &[(LineCounts, f64)]) -> LinearModel {
+        const FEATURES: usize = 2 * PATTERNS + 1;
+        // keeps the weights of counts that never come up at 0
+        const RIDGE: f64 = 1e-3;
+        let mut matrix = vec![[0.0; FEATURES + 1]; FEATURES];
+        for &(ref lines, outcome) in samples {
+            let features = features(lines);
+            for row in 0 .. FEATURES {
+                for column in 0 .. FEATURES {
+                    matrix[row][column] += features[row] * features[column];
+                }
+                matrix[row][FEATURES] += features[row] * outcome;
+            }
+        }
+        for (index, row) in matrix.iter_mut().enumerate() {
+            row[index] += RIDGE;
+        }
+        let weights = solve(&mut matrix);
+        let mut model = LinearModel {
+            bias: weights[2 * PATTERNS],
+            ..LinearModel::default()
+        };
+        model.own.copy_from_slice(&weights[.. PATTERNS]);
+        model.opponent.copy_from_slice(&weights[PATTERNS .. 2 * PATTERNS]);
+        model
+    }
+
+    pub fn load(path: &str) -> io::Result<LinearModel> {
+        LinearModel::read(&fs::read_to_string(path)?).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.write())
+    }
+
+    pub fn read(text: &str) -> Result<LinearModel, String> {
+        let mut model = LinearModel::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let parts: Vec<_> = line.split_whitespace().collect();
+            let invalid = || format!("invalid weight '{}'", line);
+            let weight = parts.last().and_then(|weight| weight.parse().ok()).ok_or_else(invalid)?;
+            let pattern = || parts.get(1)
+                .and_then(|stones| stones.parse::<usize>().ok())
+                .filter(|&stones| (1 ..= PATTERNS).contains(&stones) && parts.len() == 3)
+                .ok_or_else(invalid);
+            match parts[0] {
+                "bias" if parts.len() == 2 => model.bias = weight,
+                "own" => model.own[pattern()? - 1] = weight,
+                "opponent" => model.opponent[pattern()? - 1] = weight,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn write(&self) -> String {
+        let mut text = String::from("# weights per line a player could still complete, by the stones on it\n");
+        text += &format!("bias {}\n", self.bias);
+        for (pattern, weight) in self.own.iter().enumerate() {
+            text += &format!("own {} {}\n", pattern + 1, weight);
+        }
+        for (pattern, weight) in self.opponent.iter().enumerate() {
+            text += &format!("opponent {} {}\n", pattern + 1, weight);
+        }
+        text
+    }
+}
+
+/// Reads a dataset written by selfplay into the lines of every position with its value for the
+/// player to move: the evaluation of the search if it was proven, otherwise the outcome of the
+/// game, as an estimate is no more than a guess. Only the classic rules are decided by lines.
+pub fn training_data<R: Rules>(rules: &R, csv: &str, to_win: u32, topology: Topology)
+    -> Result<Vec<(LineCounts, f64)>, String> {
+    if rules.line_rules().filter(|rules| !rules.misere).is_none() {
+        return Err("only positions of the classic rules can be evaluated by their lines".to_string());
+    }
+    let mut samples = Vec::new();
+    // the first line names the columns
+    for (number, line) in csv.lines().enumerate().skip(1).filter(|&(_, line)| !line.trim().is_empty()) {
+        let columns: Vec<_> = line.split(',').collect();
+        let invalid = |reason: String| format!("line {}: {}", number + 1, reason);
+        if columns.len() != 5 {
+            return Err(invalid(format!("expected 5 columns, found {}", columns.len())));
+        }
+        let (grid, player) = position::setup(rules, columns[0], to_win, topology).map_err(invalid)?;
+        let value = |column: &str| match column.trim() {
+            "win" => Ok(1.0),
+            "draw" => Ok(0.0),
+            "loss" => Ok(-1.0),
+            value => Err(invalid(format!("invalid outcome '{}'", value))),
+        };
+        let outcome = match columns[1].trim() {
+            "unclear" => value(columns[4])?,
+            evaluation => value(evaluation)?,
+        };
+        samples.push((LineCounts::of_grid(&grid, player, |cell| rules.owner(cell)), outcome));
+    }
+    Ok(samples)
+}
+
+fn features(lines: &LineCounts) -> Vec<f64> {
+    let mut features: Vec<f64> = lines.own.iter().chain(lines.opponent.iter()).map(|&count| f64::from(count)).collect();
+    features.push(1.0);
+    features
+}
+
+// Solves the linear equations of an augmented matrix by Gaussian elimination.
+fn solve<T: AsMut<[f64]> + AsRef<[f64]>>(matrix: &mut [T]) -> Vec<f64> {
+    let size = matrix.len();
+    for column in 0 .. size {
+        let pivot = (column .. size)
+            .max_by(|&first, &second| matrix[first].as_ref()[column].abs()
+                .total_cmp(&matrix[second].as_ref()[column].abs()))
+            .expect("the matrix isn't empty");
+        matrix.swap(column, pivot);
+        let divisor = matrix[column].as_ref()[column];
+        for value in matrix[column].as_mut().iter_mut() {
+            *value /= divisor;
+        }
+        let pivot_row: Vec<f64> = matrix[column].as_ref().to_vec();
+        for (index, row) in matrix.iter_mut().enumerate() {
+            let factor = row.as_ref()[column];
+            if index != column && factor != 0.0 {
+                for (value, &pivot_value) in row.as_mut().iter_mut().zip(pivot_row.iter()) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+    matrix.iter().map(|row| row.as_ref()[size]).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::{Rules, LineRules, WildRules};
+    use ::game::{CellState, PlayerId, Topology};
+
+    fn counts(grid: &Grid, player: PlayerId) -> LineCounts {
+        LineCounts::of_grid(grid, player, |cell| LineRules::default().owner(cell))
+    }
+
+    #[test]
+    fn test_line_counts() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(1, 1, PlayerId(1));
+        grid.set_cell(0, 0, PlayerId(2));
+        let lines = counts(&grid, PlayerId(1));
+        // the center lies on 4 lines, one of them blocked by the corner
+        assert_eq!(3, lines.own[0]);
+        assert_eq!(2, lines.opponent[0]);
+        assert_eq!(0, lines.own[1]);
+
+        let board = BitBoard::from_grid(&grid, |cell| match *cell {
+            CellState::Set(player) => Some(player),
+            CellState::Unset => None,
+        }).unwrap();
+        assert_eq!(lines, LineCounts::of_board(&board, PlayerId(1)));
+        assert_eq!(counts(&grid, PlayerId(2)), LineCounts::of_board(&board, PlayerId(2)));
+    }
+
+    #[test]
+    fn test_heuristic_prefers_more_open_lines() {
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(1, 1, PlayerId(1));
+        let center = LineHeuristic.evaluate(&counts(&grid, PlayerId(1)));
+        assert!(center > 0.0 && center < 1.0);
+        assert!((center + LineHeuristic.evaluate(&counts(&grid, PlayerId(2)))).abs() < 1e-9);
+        assert_eq!(0.0, LineHeuristic.evaluate(&counts(&Grid::new(3, 3, 3), PlayerId(1))));
+    }
+
+    #[test]
+    fn test_fit_finds_the_weights() {
+        let model = LinearModel {
+            own: [0.1, 0.3, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            opponent: [-0.05, -0.2, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            bias: 0.02,
+        };
+        let samples: Vec<_> = (0 .. 50u32).map(|index| {
+            let mut lines = LineCounts::default();
+            lines.own[0] = index % 5;
+            lines.own[1] = index % 3;
+            lines.opponent[0] = index % 7 / 2;
+            lines.opponent[1] = index % 2;
+            let outcome = model.evaluate(&lines);
+            (lines, outcome)
+        }).filter(|&(_, outcome)| outcome.abs() < 1.0).collect();
+        let fitted = LinearModel::fit(&samples);
+        for pattern in 0 .. 2 {
+            assert!((model.own[pattern] - fitted.own[pattern]).abs() < 1e-3, "{:?}", fitted);
+            assert!((model.opponent[pattern] - fitted.opponent[pattern]).abs() < 1e-3, "{:?}", fitted);
+        }
+        assert!((model.bias - fitted.bias).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_training_data() {
+        let csv = "position,evaluation,moves,estimate,outcome\nxx./oo./... x,win,1,0.000,win\n\
+                   .../.x./... o,draw,8,0.000,draw\n.../.x./... o,unclear,2,0.000,loss\n";
+        let samples = training_data(&LineRules::default(), csv, 3, Topology::Bounded).unwrap();
+        assert_eq!(3, samples.len());
+        assert_eq!(1, samples[0].0.own[1]);
+        assert_eq!(1.0, samples[0].1);
+        assert_eq!(4, samples[1].0.opponent[0]);
+        assert_eq!(0.0, samples[1].1);
+        // an estimate isn't taken for a draw, the game decides instead
+        assert_eq!(-1.0, samples[2].1);
+
+        assert!(training_data(&LineRules::default(), "header\n... x,win", 3, Topology::Bounded).is_err());
+        assert!(training_data(&LineRules { misere: true }, csv, 3, Topology::Bounded).is_err());
+        assert!(training_data(&WildRules::default(), csv, 3, Topology::Bounded).is_err());
+    }
+
+    #[test]
+    fn test_model_file_round_trip() {
+        let mut model = LinearModel::default();
+        model.own[2] = 0.5;
+        model.opponent[0] = -0.25;
+        model.bias = 0.125;
+        assert_eq!(model, LinearModel::read(&model.write()).unwrap());
+        assert!(LinearModel::read("own 9 0.5").is_err());
+        assert!(LinearModel::read("own 1").is_err());
+        assert!(LinearModel::read("weight 1 0.5").is_err());
+    }
+}
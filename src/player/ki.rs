@@ -1,21 +1,419 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::Chain;
+use std::mem;
+use std::vec;
+use std::time::{Duration, Instant};
 use ::player::Player;
+use ::player::book::Book;
+use ::player::solver::Database;
+use ::player::evaluation::{Evaluator, LineCounts, LineHeuristic};
+use ::player::threats::Threats;
+use ::player::candidates::{self, Candidates, CANDIDATE_DISTANCE};
+use ::game::bitboard::{BitBoard, Indices, MAX_CELLS};
 use ::game::grid::Grid;
-use ::game::grid_observer;
-use ::game::{CellState, PlayerId, GameState};
+use ::game::rules::{Rules, LineRules};
+use ::game::clock::TimeControl;
+use ::game::{Cell, PlayerId, GameState, PLAYER_COUNT};
 
 pub struct KiPlayer {
     id: u32,
+    time_control: RefCell<TimeControl>,
+    // the time the game leaves the player, which its own time control can't exceed
+    game_clock: RefCell<TimeControl>,
+    book: Option<Book>,
+    database: Option<Database>,
+    // estimates the positions beyond the horizon of a search with a time limit
+    evaluator: Box<dyn Evaluator>,
+    // how far from the stones the searched moves may be, 0 for all moves, by board size if not set
+    candidate_distance: Option<u32>,
+    // the positions the latest search visited
+    nodes: RefCell<u64>,
+    // whether the value of the latest move is proven
+    exact: RefCell<bool>,
 }
 
 impl KiPlayer {
     pub fn new(id: u32) -> KiPlayer {
+        KiPlayer::with_time_control(id, TimeControl::Unlimited)
+    }
+
+    /// Creates a player which searches deeper and deeper until its time for the move is up,
+    /// then plays the best move of the deepest finished search.
+    pub fn with_time_control(id: u32, time_control: TimeControl) -> KiPlayer {
         KiPlayer {
-            id: id,
+            id,
+            time_control: RefCell::new(time_control),
+            game_clock: RefCell::new(TimeControl::Unlimited),
+            book: None,
+            database: None,
+            evaluator: Box::new(LineHeuristic),
+            candidate_distance: None,
+            nodes: RefCell::new(0),
+            exact: RefCell::new(true),
+        }
+    }
+
+    /// Plays the moves of `book` instantly while the game is still in it.
+    pub fn with_book(mut self, book: Book) -> KiPlayer {
+        self.book = Some(book);
+        self
+    }
+
+    /// Plays perfectly and instantly from a solved `database`.
+    pub fn with_database(mut self, database: Database) -> KiPlayer {
+        self.database = Some(database);
+        self
+    }
+
+    /// Estimates the positions beyond the horizon of its search with `evaluator` instead of
+    /// the hand-written `LineHeuristic`.
+    pub fn with_evaluator<E: Evaluator + 'static>(mut self, evaluator: E) -> KiPlayer {
+        self.evaluator = Box::new(evaluator);
+        self
+    }
+
+    /// Only searches the moves at most `distance` cells away from a stone in every direction,
+    /// 0 searches all moves. By default boards too large for a bitboard use `CANDIDATE_DISTANCE`.
+    pub fn with_candidate_distance(mut self, distance: u32) -> KiPlayer {
+        self.candidate_distance = Some(distance);
+        self
+    }
+
+    /// The number of positions the latest search visited, 0 if the move came from the database or book.
+    pub fn nodes(&self) -> u64 {
+        *self.nodes.borrow()
+    }
+
+    /// Whether the value of the latest move is proven, rather than estimated at the horizon of a
+    /// search that ran out of time.
+    pub fn exact(&self) -> bool {
+        *self.exact.borrow()
+    }
+
+    /// The move the player would make on `grid`, from its database, its book or by searching.
+    pub fn best_move<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) -> R::Move {
+        self.evaluated_move(rules, grid).0
+    }
+
+    /// The best move with its value for the player, as far as it is known: a search with a time
+    /// limit estimates the positions beyond its horizon, and book moves come without a value.
+    pub fn evaluated_move<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) -> (R::Move, Option<Value>) {
+        let player = PlayerId(self.id);
+        *self.nodes.borrow_mut() = 0;
+        *self.exact.borrow_mut() = true;
+        if let Some(ref database) = self.database {
+            if let Some(mov) = database.best_move(rules, grid, player) {
+                return (mov, database.value(rules, grid, player));
+            }
+        }
+        if let Some(mov) = self.book.as_ref().and_then(|book| book.lookup(rules, grid, player)) {
+            return (mov, None);
+        }
+        let start = Instant::now();
+        let moves_left = grid.empty_cells().count() as u32 / PLAYER_COUNT;
+        let budget = match (self.time_control.borrow().budget(moves_left), self.game_clock.borrow().budget(moves_left)) {
+            (Some(own), Some(game)) => Some(own.min(game)),
+            (own, game) => own.or(game),
+        };
+        let deadline = budget.map(|budget| start + budget);
+
+        // a search with a horizon misses long forced wins, so those are looked for by forcing moves first
+        let mut root_cells = None;
+        let threats = rules.line_rules().filter(|line_rules| !line_rules.misere && deadline.is_some())
+            .and_then(|_| Threats::from_grid(grid, |cell| rules.owner(cell)));
+        if let Some(mut threats) = threats {
+            if let Some(sequence) = threats.victory_by_fours(player) {
+                if let Some(mov) = placing_move(rules, grid, player, sequence[0]) {
+                    let value = Value {
+                        evaluation: GameEvaluation::Win,
+                        moves: sequence.len() as u32,
+                        estimate: 0,
+                    };
+                    return (mov, Some(value));
+                }
+            }
+            // the moves that stop the forced wins of the opponent are the only ones worth searching
+            root_cells = threats.defences(player);
+        }
+
+        // large boards have too many moves to look at every one of them
+        let default_distance = if grid.cell_count() > MAX_CELLS { CANDIDATE_DISTANCE } else { 0 };
+        let candidates = match self.candidate_distance.unwrap_or(default_distance) {
+            0 => None,
+            distance => Some(Candidates::new(grid, distance)),
+        };
+
+        // the classic rules are searched on bitboards, which is a lot faster
+        let board = rules.line_rules().and_then(|line_rules| {
+            BitBoard::from_grid(grid, |cell| rules.owner(cell)).map(|board| BitPosition {
+                rules: line_rules,
+                board,
+                candidates: candidates.clone(),
+            })
+        });
+        let (best, nodes) = match board {
+            Some(position) => {
+                let (best, nodes) = deepen(&position, player, deadline, &*self.evaluator, root_cells);
+                let best = best.and_then(|(index, value, exact)| placing_move(rules, grid, player, index)
+                    .map(|mov| (mov, value, exact)));
+                (best, nodes)
+            },
+            None => {
+                let position = GridPosition {
+                    rules,
+                    grid: grid.clone(),
+                    candidates,
+                };
+                deepen(&position, player, deadline, &*self.evaluator, root_cells)
+            },
+        };
+        *self.nodes.borrow_mut() = nodes;
+        match best {
+            Some((mov, value, exact)) => {
+                *self.exact.borrow_mut() = exact;
+                (mov, Some(value))
+            },
+            None => (rules.legal_moves(grid, player)[0].clone(), None),
+        }
+    }
+}
+
+// Searches deeper and deeper until the deadline passes, or everything at once without one,
+// and returns the best move and value of the deepest finished search, with whether the value is
+// proven, and the number of positions visited.
+// With `root_cells` only the moves placing on one of these cells are searched.
+fn deepen<P: Position>(position: &P, player: PlayerId, deadline: Option<Instant>, evaluator: &dyn Evaluator,
+                       root_cells: Option<Vec<usize>>) -> (Option<(P::Move, Value, bool)>, u64) {
+    // without a deadline there is no need to deepen step by step, just search everything
+    let first_depth = if deadline.is_some() { 1 } else { u32::MAX };
+    let mut best = None;
+    let mut search = Search::new(first_depth, deadline, evaluator);
+    search.root_cells = root_cells;
+    for max_depth in first_depth ..= u32::MAX {
+        search.max_depth = max_depth;
+        search.horizon_reached = false;
+        match minimax(position, player, 0, &mut search) {
+            Some(outcome) => {
+                let value = outcome.value;
+                let exact = value.evaluation != GameEvaluation::Draw || !search.horizon_reached;
+                best = outcome.mov.map(|mov| (mov, value, exact));
+                if exact {
+                    break;
+                }
+            },
+            None => break,
+        }
+    }
+    (best, search.nodes)
+}
+
+// The move of `player` that places something on the cell at `index`.
+fn placing_move<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: PlayerId, index: usize) -> Option<R::Move> {
+    rules.legal_moves(grid, player).into_iter().find(|mov| {
+        let mut new_grid = grid.clone();
+        rules.apply_move(&mut new_grid, player, mov) && *new_grid.get_cell_by_index(index) != R::Cell::unset()
+    })
+}
+
+// A position the search works on, so the same search runs on grids of any rules and on bitboards.
+trait Position: Clone {
+    type Move: Clone;
+    // the moves of a position, bitboards hand them out without allocating
+    type Moves: Iterator<Item = Self::Move>;
+    // the same for positions that only differ by a rotation or reflection
+    type Key: Hash + Eq;
+
+    fn evaluate(&self, perspective: PlayerId) -> Option<GameEvaluation>;
+    // the moves worth searching, the most promising first
+    fn moves(&self, player: PlayerId) -> Self::Moves;
+    // every legal move
+    fn all_moves(&self, player: PlayerId) -> Self::Moves;
+    fn play(&mut self, player: PlayerId, mov: &Self::Move);
+    fn next_player(&self, player: PlayerId) -> PlayerId;
+    fn key(&self) -> Self::Key;
+    // the lines both players could still complete, none if lines don't decide the game that way
+    fn line_counts(&self, player: PlayerId) -> Option<LineCounts>;
+    // the index of the cell `mov` places on
+    fn cell_of(&self, player: PlayerId, mov: &Self::Move) -> Option<usize>;
+}
+
+struct GridPosition<'a, R: Rules + 'a> {
+    rules: &'a R,
+    grid: Grid<R::Cell>,
+    // the cells moves are limited to, all if none
+    candidates: Option<Candidates>,
+}
+
+impl<'a, R: Rules> Clone for GridPosition<'a, R> {
+    fn clone(&self) -> Self {
+        GridPosition {
+            rules: self.rules,
+            grid: self.grid.clone(),
+            candidates: self.candidates.clone(),
+        }
+    }
+}
+
+impl<'a, R: Rules> Position for GridPosition<'a, R> {
+    type Move = R::Move;
+    type Moves = vec::IntoIter<R::Move>;
+    type Key = Grid<R::Cell>;
+
+    fn evaluate(&self, perspective: PlayerId) -> Option<GameEvaluation> {
+        evaluate_game(self.rules, &self.grid, perspective)
+    }
+
+    fn moves(&self, player: PlayerId) -> vec::IntoIter<R::Move> {
+        let candidates = match self.candidates {
+            Some(ref candidates) => candidates,
+            None => return self.all_moves(player),
+        };
+        let mut cells = candidates.cells(&self.grid);
+        if self.rules.line_rules().is_some_and(|rules| !rules.misere) {
+            cells = candidates::order_by_threats(&self.grid, player, cells, |cell| self.rules.owner(cell));
+        }
+        let mut by_cell = vec![Vec::new(); self.grid.cell_count()];
+        for mov in self.rules.legal_moves(&self.grid, player) {
+            if let Some(cell) = self.cell_of(player, &mov) {
+                by_cell[cell].push(mov);
+            }
+        }
+        let moves: Vec<_> = cells.into_iter().flat_map(|cell| mem::take(&mut by_cell[cell])).collect();
+        moves.into_iter()
+    }
+
+    fn all_moves(&self, player: PlayerId) -> vec::IntoIter<R::Move> {
+        self.rules.legal_moves(&self.grid, player).into_iter()
+    }
+
+    fn play(&mut self, player: PlayerId, mov: &R::Move) {
+        self.rules.apply_move(&mut self.grid, player, mov);
+    }
+
+    fn next_player(&self, player: PlayerId) -> PlayerId {
+        self.rules.next_player(player)
+    }
+
+    fn key(&self) -> Grid<R::Cell> {
+        self.grid.canonical()
+    }
+
+    fn line_counts(&self, player: PlayerId) -> Option<LineCounts> {
+        self.rules.line_rules().filter(|rules| !rules.misere)
+            .map(|_| LineCounts::of_grid(&self.grid, player, |cell| self.rules.owner(cell)))
+    }
+
+    fn cell_of(&self, player: PlayerId, mov: &R::Move) -> Option<usize> {
+        if let Some(index) = self.rules.move_cell(mov) {
+            return Some(index);
+        }
+        // rules that can't tell show it by making the move
+        let mut grid = self.grid.clone();
+        self.rules.apply_move(&mut grid, player, mov);
+        (0 .. grid.cell_count()).find(|&index| grid.get_cell_by_index(index) != self.grid.get_cell_by_index(index))
+    }
+}
+
+// A position of the classic rules, the moves being the indices of the cells to place on.
+#[derive(Clone)]
+struct BitPosition {
+    rules: LineRules,
+    board: BitBoard,
+    candidates: Option<Candidates>,
+}
+
+impl Position for BitPosition {
+    type Move = usize;
+    // the cells to try first and the rest
+    type Moves = Chain<Indices, Indices>;
+    type Key = [u64; PLAYER_COUNT as usize];
+
+    fn evaluate(&self, perspective: PlayerId) -> Option<GameEvaluation> {
+        evaluation_of(self.rules.bitboard_state(&self.board), perspective)
+    }
+
+    fn moves(&self, player: PlayerId) -> Chain<Indices, Indices> {
+        let (first, rest) = match self.candidates {
+            None => return self.all_moves(player),
+            Some(ref candidates) if self.rules.misere => (candidates.mask(&self.board), 0),
+            Some(ref candidates) => candidates::threat_masks(&self.board, player, candidates.mask(&self.board)),
+        };
+        Indices(first).chain(Indices(rest))
+    }
+
+    fn all_moves(&self, _player: PlayerId) -> Chain<Indices, Indices> {
+        self.board.empty_cells().chain(Indices(0))
+    }
+
+    fn play(&mut self, player: PlayerId, index: &usize) {
+        self.board.set_cell_by_index(*index, player);
+    }
+
+    fn next_player(&self, player: PlayerId) -> PlayerId {
+        self.rules.next_player(player)
+    }
+
+    fn key(&self) -> [u64; PLAYER_COUNT as usize] {
+        self.board.canonical().key()
+    }
+
+    fn line_counts(&self, player: PlayerId) -> Option<LineCounts> {
+        if self.rules.misere {
+            None
+        } else {
+            Some(LineCounts::of_board(&self.board, player))
         }
     }
+
+    fn cell_of(&self, _player: PlayerId, index: &usize) -> Option<usize> {
+        Some(*index)
+    }
+}
+
+// The limits of a single search of the iterative deepening.
+struct Search<'a, K: Hash + Eq> {
+    max_depth: u32,
+    deadline: Option<Instant>,
+    // estimates the positions cut off at `max_depth`
+    evaluator: &'a dyn Evaluator,
+    // the cells the moves at the root have to place on, if not all of them
+    root_cells: Option<Vec<usize>>,
+    // whether an undecided position was cut off at `max_depth`, so a deeper search may do better
+    horizon_reached: bool,
+    // the exact results of positions searched to the end, by the player to move and the
+    // key of the position, with the number of blunders of the player to move
+    table: HashMap<(u32, K), (Value, u32)>,
+    // the positions visited, for benchmarks
+    nodes: u64,
+}
+
+impl<'a, K: Hash + Eq> Search<'a, K> {
+    fn new(max_depth: u32, deadline: Option<Instant>, evaluator: &'a dyn Evaluator) -> Search<'a, K> {
+        Search {
+            max_depth,
+            deadline,
+            evaluator,
+            root_cells: None,
+            horizon_reached: false,
+            table: HashMap::new(),
+            nodes: 0,
+        }
+    }
+}
+
+// The result of searching a position, from the perspective of the player to move.
+struct Outcome<M> {
+    value: Value,
+    mov: Option<M>,
+    // how many moves would lose a position that isn't lost, the chances to go wrong
+    blunders: u32,
 }
 
 #[derive(Debug)]
+#[derive(Copy, Clone)]
 #[derive(PartialEq)]
 pub enum GameEvaluation {
     Win,
@@ -23,25 +421,239 @@ pub enum GameEvaluation {
     Draw,
 }
 
-#[derive(Clone)]
-struct Move {
-    pub row: usize,
-    pub column: usize,
+/// How long an analysis of a position searches before settling for estimates.
+pub const ANALYSIS_TIME: Duration = Duration::from_secs(1);
+
+/// A legal move and where it leads with best play from both sides.
+#[derive(Debug)]
+pub struct MoveAnalysis<M> {
+    pub mov: M,
+    // from the perspective of the player making the move, counting the move itself
+    pub value: Value,
+    // whether the value is proven, otherwise the search ran out of time and it is an estimate
+    pub exact: bool,
+    // the best play of both players, starting with the move, or just the move if the value is an estimate
+    pub variation: Vec<M>,
+}
+
+impl<M> MoveAnalysis<M> {
+    /// The value, or its estimate if the search couldn't prove it.
+    pub fn describe_value(&self) -> String {
+        self.value.describe(self.exact)
+    }
+}
+
+/// The result of a position with best play from both sides.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub struct Value {
+    // from the perspective of the player to move
+    pub evaluation: GameEvaluation,
+    // how many moves are left until the game ends, the winner ends it as fast as possible
+    // and the loser drags it out as long as possible
+    pub moves: u32,
+    // for a draw cut off by the horizon of the search, how good the position looked,
+    // in thousandths from -1000 for lost to 1000 for won
+    pub estimate: i32,
+}
+
+impl Value {
+    /// The value of a finished game.
+    pub fn end(evaluation: GameEvaluation) -> Value {
+        Value {
+            evaluation,
+            moves: 0,
+            estimate: 0,
+        }
+    }
+
+    /// The value of an undecided position at the horizon of a search, from -1 for lost to 1 for won.
+    pub fn estimate(estimate: f64) -> Value {
+        Value {
+            evaluation: GameEvaluation::Draw,
+            moves: 0,
+            estimate: (estimate.clamp(-1.0, 1.0) * 1000.0).round() as i32,
+        }
+    }
+
+    /// The value of the position before the move that led to this one.
+    pub fn for_parent(&self) -> Value {
+        let evaluation = match self.evaluation {
+            GameEvaluation::Win => GameEvaluation::Lose,
+            GameEvaluation::Lose => GameEvaluation::Win,
+            GameEvaluation::Draw => GameEvaluation::Draw,
+        };
+        Value {
+            evaluation,
+            moves: self.moves + 1,
+            estimate: -self.estimate,
+        }
+    }
+
+    /// Higher is better for the player to move: the faster a win or the slower a loss the better.
+    pub fn score(&self) -> i64 {
+        let moves = i64::from(self.moves);
+        match self.evaluation {
+            GameEvaluation::Win => i64::from(u32::MAX) - moves,
+            GameEvaluation::Draw => i64::from(self.estimate),
+            GameEvaluation::Lose => moves - i64::from(u32::MAX),
+        }
+    }
+
+    /// The value as text, or its estimate if it isn't `exact`.
+    pub fn describe(&self, exact: bool) -> String {
+        if exact {
+            self.to_string()
+        } else {
+            describe_estimate(self.estimate)
+        }
+    }
+}
+
+impl fmt::Display for GameEvaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GameEvaluation::Win => write!(f, "win"),
+            GameEvaluation::Draw => write!(f, "draw"),
+            GameEvaluation::Lose => write!(f, "loss"),
+        }
+    }
 }
 
-impl Move {
-    fn new(row: usize, column: usize) -> Move {
-        Move {
-            row: row,
-            column: column,
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.evaluation {
+            GameEvaluation::Win => write!(f, "win in {}", self.moves),
+            GameEvaluation::Lose => write!(f, "loss in {}", self.moves),
+            GameEvaluation::Draw if self.estimate != 0 => write!(f, "{}", describe_estimate(self.estimate)),
+            GameEvaluation::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+fn describe_estimate(estimate: i32) -> String {
+    format!("unclear, estimated {:+.3}", f64::from(estimate) / 1000.0)
+}
+
+/// Evaluates every legal move of `player`, best first. Without a time limit the moves are
+/// searched to the end of the game, which is only feasible on small boards. With one, the search
+/// deepens until the time is up and the values it couldn't prove by then are estimates.
+pub fn analyze<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: PlayerId, time_limit: Option<Duration>)
+    -> Vec<MoveAnalysis<R::Move>> {
+    let start = Instant::now();
+    let candidates = if grid.cell_count() > MAX_CELLS { Some(Candidates::new(grid, CANDIDATE_DISTANCE)) } else { None };
+    let next_player = rules.next_player(player);
+    let moves = rules.legal_moves(grid, player);
+    // solving small boards outright is a lot faster than deepening step by step, so that gets
+    // the first half of the time
+    let mut search = Search::new(u32::MAX, time_limit.map(|limit| start + limit / 2), &LineHeuristic);
+    let mut values = search_moves(rules, grid, player, &moves, candidates.as_ref(), &mut search);
+    if let Some(limit) = time_limit.filter(|_| values.is_none()) {
+        search.deadline = Some(start + limit);
+        for max_depth in 1 .. {
+            search.max_depth = max_depth;
+            // the values of a search the deadline cut off are incomplete, the shallower ones count
+            let depth_values = match search_moves(rules, grid, player, &moves, candidates.as_ref(), &mut search) {
+                Some(depth_values) => depth_values,
+                None => break,
+            };
+            let exact = depth_values.iter().all(|&(_, exact)| exact);
+            values = Some(depth_values);
+            if exact {
+                break;
+            }
+        }
+    }
+    let values = values.expect("A search to depth 1 didn't finish.");
+    let mut analyses: Vec<_> = moves.into_iter().zip(values).map(|(mov, (value, exact))| {
+        let mut new_grid = grid.clone();
+        rules.apply_move(&mut new_grid, player, &mov);
+        let mut variation = vec![mov.clone()];
+        if exact {
+            principal_variation(rules, &new_grid, next_player, &search, &mut variation);
+        }
+        MoveAnalysis {
+            mov,
+            value,
+            exact,
+            variation,
+        }
+    }).collect();
+    analyses.sort_by_key(|analysis| -analysis.value.score());
+    analyses
+}
+
+// The values of all `moves` of `player` within the depth limit of `search` and whether they are
+// proven, or None if the deadline passed.
+fn search_moves<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: PlayerId, moves: &[R::Move],
+                          candidates: Option<&Candidates>, search: &mut Search<'_, Grid<R::Cell>>)
+    -> Option<Vec<(Value, bool)>> {
+    let next_player = rules.next_player(player);
+    moves.iter().map(|mov| {
+        let mut new_grid = grid.clone();
+        rules.apply_move(&mut new_grid, player, mov);
+        let position = GridPosition {
+            rules,
+            grid: new_grid,
+            candidates: candidates.cloned(),
+        };
+        search.horizon_reached = false;
+        let outcome = minimax(&position, next_player, 1, search)?;
+        // wins and losses are proven even beyond the horizon, only estimates are not
+        let exact = outcome.value.evaluation != GameEvaluation::Draw || !search.horizon_reached;
+        Some((outcome.value.for_parent(), exact))
+    }).collect()
+}
+
+// Follows the best moves through the results of `search` until the game ends.
+fn principal_variation<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: PlayerId,
+                                 search: &Search<'_, Grid<R::Cell>>, variation: &mut Vec<R::Move>) {
+    let mut grid = grid.clone();
+    let mut player = player;
+    while evaluate_game(rules, &grid, player).is_none() {
+        let next_player = rules.next_player(player);
+        let PlayerId(next_id) = next_player;
+        let mut best: Option<(R::Move, Value, u32)> = None;
+        for mov in rules.legal_moves(&grid, player) {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, &mov);
+            let result = match evaluate_game(rules, &new_grid, next_player) {
+                Some(evaluation) => Some((Value::end(evaluation), 0)),
+                None => search.table.get(&(next_id, new_grid.canonical())).cloned(),
+            };
+            // positions after an immediate win might not have been searched
+            if let Some((value, blunders)) = result {
+                let value = value.for_parent();
+                let better = match best {
+                    None => true,
+                    Some((_, best_value, best_blunders)) => value.score() > best_value.score()
+                        || (value.score() == best_value.score() && blunders > best_blunders),
+                };
+                if better {
+                    best = Some((mov, value, blunders));
+                }
+            }
+        }
+        match best {
+            Some((mov, _, _)) => {
+                rules.apply_move(&mut grid, player, &mov);
+                variation.push(mov);
+                player = next_player;
+            },
+            None => break,
         }
     }
 }
 
 //TODO: cache evaluations and just look at differences
-//TODO: benchmark
-pub fn evaluate_game(grid: &Grid, perspective: PlayerId) -> Option<GameEvaluation> {
-    match grid_observer::check_winner(grid) {
+pub fn evaluate_game<R: Rules>(rules: &R, grid: &Grid<R::Cell>, perspective: PlayerId)
+    -> Option<GameEvaluation> {
+    evaluation_of(rules.game_state(grid), perspective)
+}
+
+fn evaluation_of(state: GameState, perspective: PlayerId) -> Option<GameEvaluation> {
+    match state {
         GameState::Mid => None,
         GameState::Win(winner) => {
             if winner == perspective {
@@ -50,63 +662,149 @@ pub fn evaluate_game(grid: &Grid, perspective: PlayerId) -> Option<GameEvaluatio
                 Some(GameEvaluation::Lose)
             }
         },
+        GameState::Lose(loser) | GameState::LossOnTime(loser) => {
+            if loser == perspective {
+                Some(GameEvaluation::Lose)
+            } else {
+                Some(GameEvaluation::Win)
+            }
+        },
         GameState::Draw => Some(GameEvaluation::Draw)
     }
 }
 
+// Positions beyond the depth limit are estimated by the evaluator of the search, or count as
+// draws if it can't judge them. Returns None if the deadline passed.
+// Among equally good moves the one leaving the opponent the most chances to blunder is taken.
 //TODO: Implement different difficulties
 // less difficulty: lower depth limit
 // if no move can be found within the depth limit, chose a random move
 // maybe the depth limit can be a percentage of the maximum depth?
-//TODO: Cleaner return value. Maybe a GameEnd enum with Draw or Winner(PlayerId) as options?
-fn minimax(grid: &Grid, current_player: PlayerId, other_player: PlayerId, depth: u32)
-    -> (GameEvaluation, Option<Move>, u32) {
-    let evaluation = evaluate_game(grid, current_player);
-
-    if evaluation.is_some() {
-        (evaluation.unwrap(), None, depth)
+fn minimax<P: Position>(position: &P, current_player: PlayerId, depth: u32,
+                        search: &mut Search<'_, P::Key>) -> Option<Outcome<P::Move>> {
+    search.nodes += 1;
+    if let Some(evaluation) = position.evaluate(current_player) {
+        return Some(Outcome {
+            value: Value::end(evaluation),
+            mov: None,
+            blunders: 0,
+        });
+    }
+    let PlayerId(id) = current_player;
+    let key = (id, position.key());
+    // the root always needs a move, so it is searched even if its result is known
+    if let Some(&(value, blunders)) = search.table.get(&key).filter(|_| depth > 0) {
+        Some(Outcome {
+            value,
+            mov: None,
+            blunders,
+        })
+    } else if depth >= search.max_depth {
+        search.horizon_reached = true;
+        let value = match position.line_counts(current_player) {
+            Some(lines) => Value::estimate(search.evaluator.evaluate(&lines)),
+            None => Value::end(GameEvaluation::Draw),
+        };
+        Some(Outcome {
+            value,
+            mov: None,
+            blunders: 0,
+        })
+    } else if search.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        None
     } else {
-        let mut wins: Vec<(Move, u32)> = Vec::new();
-        let mut draws: Vec<(Move, u32)> = Vec::new();
-        let mut loses: Vec<(Move, u32)> = Vec::new();
+        let mut best: Option<(P::Move, Value, u32)> = None;
+        let mut blunders = 0;
+        let other_player = position.next_player(current_player);
+        let horizon_reached = search.horizon_reached;
+        search.horizon_reached = false;
 
-        for (row, col) in grid.get_cells_with_state(CellState::Unset) {
+        let mut distinct;
+        let mut all;
+        let moves: &mut dyn Iterator<Item = P::Move> = if depth == 0 {
+            distinct = distinct_moves(position, current_player, &search.root_cells).into_iter();
+            &mut distinct
+        } else {
+            all = position.moves(current_player);
+            &mut all
+        };
+        for mov in moves {
             //TODO: Use multiple threads
-            let mut new_grid = grid.clone();
-            new_grid.set_cell(row, col, current_player);
-            let (evaluation, _, depth) = minimax(&new_grid, other_player, current_player, depth + 1);
-            match evaluation {
-                //TODO: Stop mixing up perspectives
-                GameEvaluation::Lose => wins.push((Move::new(row, col), depth)),
-                GameEvaluation::Draw => draws.push((Move::new(row, col), depth)),
-                GameEvaluation::Win => loses.push((Move::new(row, col), depth)),
+            let mut new_position = position.clone();
+            new_position.play(current_player, &mov);
+            let outcome = minimax(&new_position, other_player, depth + 1, search)?;
+            let value = outcome.value.for_parent();
+            if value.evaluation == GameEvaluation::Lose {
+                blunders += 1;
+            }
+            let better = match best {
+                None => true,
+                Some((_, best_value, opponent_blunders)) => value.score() > best_value.score()
+                    || (value.score() == best_value.score() && outcome.blunders > opponent_blunders),
+            };
+            if better {
+                best = Some((mov, value, outcome.blunders));
+            }
+            // nothing is faster than winning right away
+            if value.evaluation == GameEvaluation::Win && value.moves == 1 {
+                break;
             }
         }
 
-        if !wins.is_empty() {
-            let (mov, depth) = wins[0].clone();
-            (GameEvaluation::Win, Some(mov), depth)
-        } else if !draws.is_empty() {
-            let (mov, depth) = draws[0].clone();
-            (GameEvaluation::Draw, Some(mov), depth)
-        } else if !loses.is_empty() {
-            let (mov, depth) = loses[0].clone();
-            (GameEvaluation::Lose, Some(mov), depth)
-        } else {
-            panic!("No possible move, even though the game shouldn't be finished.");
+        let (mov, value, _) = best.expect("No possible move, even though the game shouldn't be finished.");
+        if value.evaluation == GameEvaluation::Lose {
+            blunders = 0;
+        }
+        // results below the depth limit may change with a deeper search, so they aren't kept
+        if !search.horizon_reached {
+            search.table.insert(key, (value, blunders));
+        }
+        search.horizon_reached |= horizon_reached;
+        Some(Outcome {
+            value,
+            mov: Some(mov),
+            blunders,
+        })
+    }
+}
+
+// The legal moves without those leading to a position that is just a rotation or reflection
+// of the position after an earlier move, like the corners of an empty board. With `cells`
+// only the moves placing on one of them count.
+fn distinct_moves<P: Position>(position: &P, player: PlayerId, cells: &Option<Vec<usize>>) -> Vec<P::Move> {
+    let mut seen = Vec::new();
+    let mut moves = Vec::new();
+    // the allowed cells may lie outside of the moves worth searching otherwise
+    let candidates = match *cells {
+        Some(_) => position.all_moves(player),
+        None => position.moves(player),
+    };
+    let allowed = |mov: &P::Move| match *cells {
+        Some(ref cells) => position.cell_of(player, mov).is_some_and(|cell| cells.contains(&cell)),
+        None => true,
+    };
+    for mov in candidates.filter(allowed) {
+        let mut new_position = position.clone();
+        new_position.play(player, &mov);
+        let key = new_position.key();
+        if !seen.contains(&key) {
+            seen.push(key);
+            moves.push(mov);
         }
     }
+    moves
+}
 
+impl<R: Rules> Player<R> for KiPlayer {
+    fn make_turn (&self, rules: &R, grid: &mut Grid<R::Cell>) {
+        let start = Instant::now();
+        let mov = self.best_move(rules, grid);
+        rules.apply_move(grid, PlayerId(self.id), &mov);
+        self.time_control.borrow_mut().spend(start.elapsed());
     }
 
-impl Player for KiPlayer {
-    fn make_turn (&self, grid: &mut Grid) {
-        //TODO: Proper way to determine other player (Array of players?)
-        let other_id = if self.id == 1 {2} else {1};
-        let (_, position, _) = minimax(grid, PlayerId(self.id), PlayerId(other_id), 0);
-        // TODO: don't use unwrap
-        let position = position.unwrap();
-        grid.set_cell(position.row, position.column, PlayerId(self.id));
+    fn update_clocks(&self, clocks: &[TimeControl]) {
+        *self.game_clock.borrow_mut() = clocks[self.id as usize - 1];
     }
 }
 
@@ -114,9 +812,15 @@ impl Player for KiPlayer {
 mod test {
     use super::*;
     use ::player::Player;
-    use ::game::{CellState, PlayerId};
+    use ::game::{CellState, NumberCell, PlayerId, Topology};
     use ::game::grid::Grid;
     use ::game::grid_observer;
+    use ::game::position;
+    use ::game::rules::{LineRules, WildRules, NumericalRules};
+    use ::game::clock::{Clock, TimeControl};
+    use ::player::solver::Database;
+    use ::player::evaluation::LinearModel;
+    use std::time::{Duration, Instant};
 
 
     #[test]
@@ -124,43 +828,38 @@ mod test {
         const KI_ID: u32 = 1;
         let mut grid = Grid::new(1, 1, 1);
         let ki = KiPlayer::new(KI_ID);
-        ki.make_turn(&mut grid);
-        match grid.get_cell(0, 0) {
-            &CellState::Unset => panic!("The ki didn't do anything."),
-            &CellState::Set(PlayerId(KI_ID)) => return,
-            &CellState::Set(PlayerId(id)) =>
+        ki.make_turn(&LineRules::default(), &mut grid);
+        match *grid.get_cell(0, 0) {
+            CellState::Unset => panic!("The ki didn't do anything."),
+            CellState::Set(PlayerId(KI_ID)) => (),
+            CellState::Set(PlayerId(id)) =>
                 panic!("The ki with the {} made a turn for player {}.", KI_ID, id),
         }
     }
 
-    // TODO: Make tests work
-    // #[test]
-    // fn ki_makes_winning_move() {
-    //     const KI_ID: u32 = 1;
-    //     let mut grid = Grid::new(10, 10, 2);
-    //     grid.set_cell(5, 5, PlayerId(KI_ID));
-    //     KiPlayer::new(KI_ID).make_turn(&mut grid);
+    #[test]
+    fn ki_makes_winning_move() {
+        const KI_ID: u32 = 1;
+        let rules = LineRules::default();
+        let (mut grid, _) = position::setup(&rules, "...../.o.../..x../...../.....", 2, Topology::Bounded).unwrap();
+        KiPlayer::new(KI_ID).make_turn(&rules, &mut grid);
 
-    //     match grid_observer::check_winner(&grid) {
-    //         None => panic!("The Ki didn't make the obvious winning move"),
-    //         Some(PlayerId(id)) if id != KI_ID => panic!("The Ki somehow managed to lose"),
-    //         _ => {},
-    //     }
-    // }
+        match grid_observer::check_winner(&grid) {
+            GameState::Win(PlayerId(KI_ID)) => {},
+            GameState::Win(_) => panic!("The Ki somehow managed to lose"),
+            _ => panic!("The Ki didn't make the obvious winning move"),
+        }
+    }
 
-    // #[test]
-    // fn ki_blocks() {
-    //     const KI_ID: u32 = 1;
-    //     const OPPONENT_ID: u32 = 2;
-    //     let mut grid = Grid::new(3, 3, 3);
-    //     grid.set_cell(1, 0, PlayerId(OPPONENT_ID));
-    //     grid.set_cell(1, 1, PlayerId(OPPONENT_ID));
-    //     let ki = KiPlayer::new(KI_ID);
-    //     ki.make_turn(&mut grid);
+    #[test]
+    fn ki_blocks() {
+        const KI_ID: u32 = 1;
+        let rules = LineRules::default();
+        let (mut grid, _) = position::setup(&rules, "x../oo./..x", 3, Topology::Bounded).unwrap();
+        KiPlayer::new(KI_ID).make_turn(&rules, &mut grid);
 
-    //     grid.pretty_print();
-    //     assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(1, 2));
-    // }
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(1, 2));
+    }
 
     #[test]
     fn ki_wins_without_active_opponent() {
@@ -168,7 +867,7 @@ mod test {
         let mut grid = Grid::new(3, 3, 3);
         let ki = KiPlayer::new(KI_ID);
         for _ in 0 .. grid.to_win {
-            ki.make_turn(&mut grid);
+            ki.make_turn(&LineRules::default(), &mut grid);
         }
 
         match grid_observer::check_winner(&grid) {
@@ -179,13 +878,495 @@ mod test {
         }
     }
 
+    #[test]
+    fn ki_wins_without_active_opponent_in_three_dimensions() {
+        const KI_ID: u32 = 1;
+        let mut grid = Grid::with_dimensions(&[2, 2, 2], 2);
+        let ki = KiPlayer::new(KI_ID);
+        for _ in 0 .. grid.to_win {
+            ki.make_turn(&LineRules::default(), &mut grid);
+        }
+
+        match grid_observer::check_winner(&grid) {
+            GameState::Win(PlayerId(KI_ID)) => {},
+            _ => panic!("The Ki didn't win on a 2x2x2 board without an opponent."),
+        }
+    }
+
+    #[test]
+    fn ki_wins_around_the_edge_of_toroidal_grid() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let mut grid = Grid::with_topology(&[1, 5], 3, Topology::Toroidal);
+        grid.set_cell(0, 4, PlayerId(KI_ID));
+        grid.set_cell(0, 0, PlayerId(KI_ID));
+        grid.set_cell(0, 2, PlayerId(OPPONENT_ID));
+        KiPlayer::new(KI_ID).make_turn(&LineRules::default(), &mut grid);
+
+        match grid_observer::check_winner(&grid) {
+            GameState::Win(PlayerId(KI_ID)) => {},
+            _ => panic!("The Ki didn't complete the line around the edge."),
+        }
+    }
+
+    #[test]
+    fn ki_avoids_completing_line_in_misere() {
+        const KI_ID: u32 = 1;
+        let mut grid = Grid::new(1, 4, 3);
+        grid.set_cell(0, 0, PlayerId(KI_ID));
+        grid.set_cell(0, 1, PlayerId(KI_ID));
+        KiPlayer::new(KI_ID).make_turn(&LineRules { misere: true }, &mut grid);
+
+        assert_eq!(CellState::Unset, *grid.get_cell(0, 2));
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(0, 3));
+    }
+
+    #[test]
+    fn ki_completes_opponents_symbols_in_wild() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(1, 0, PlayerId(OPPONENT_ID));
+        grid.set_cell(1, 1, PlayerId(OPPONENT_ID));
+        grid.set_cell(0, 0, PlayerId(KI_ID));
+        grid.set_cell(2, 2, PlayerId(KI_ID));
+        let rules = WildRules::default();
+        KiPlayer::new(KI_ID).make_turn(&rules, &mut grid);
+
+        match rules.game_state(&grid) {
+            GameState::Win(PlayerId(KI_ID)) => {},
+            _ => panic!("The Ki didn't complete the line of the opponent's symbols."),
+        }
+    }
+
+    #[test]
+    fn ki_completes_sum_in_numerical() {
+        const KI_ID: u32 = 2;
+        let mut grid = Grid::<NumberCell>::create(&[3, 3], 3, Topology::Bounded);
+        grid.place(0, NumberCell::Set(9), PlayerId(1));
+        grid.place(1, NumberCell::Set(2), PlayerId(2));
+        grid.place(8, NumberCell::Set(1), PlayerId(1));
+        KiPlayer::new(KI_ID).make_turn(&NumericalRules::default(), &mut grid);
+
+        match grid_observer::check_sum(&grid) {
+            GameState::Win(PlayerId(KI_ID)) => {},
+            _ => panic!("The Ki didn't complete a line adding up to 15."),
+        }
+    }
+
+    #[test]
+    fn ki_with_time_limit_makes_winning_move_on_large_board() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let mut grid = Grid::new(6, 6, 4);
+        grid.set_cell(2, 1, PlayerId(KI_ID));
+        grid.set_cell(2, 2, PlayerId(KI_ID));
+        grid.set_cell(2, 3, PlayerId(KI_ID));
+        grid.set_cell(2, 0, PlayerId(OPPONENT_ID));
+        grid.set_cell(3, 3, PlayerId(OPPONENT_ID));
+        grid.set_cell(4, 3, PlayerId(OPPONENT_ID));
+        let ki = KiPlayer::with_time_control(KI_ID, TimeControl::PerMove(Duration::from_millis(100)));
+        let start = Instant::now();
+        ki.make_turn(&LineRules::default(), &mut grid);
+
+        assert!(start.elapsed() < Duration::from_secs(2), "The Ki didn't stop in time");
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(2, 4));
+    }
+
+    #[test]
+    fn ki_finishes_within_move_time() {
+        let mut grid = Grid::new(7, 7, 4);
+        grid.set_cell(3, 3, PlayerId(1));
+        let mut time_control = TimeControl::PerMove(Duration::from_millis(50));
+        let ki = KiPlayer::with_time_control(2, time_control);
+        let start = Instant::now();
+        ki.make_turn(&LineRules::default(), &mut grid);
+
+        assert!(time_control.spend(start.elapsed()), "The Ki took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn ki_with_clock_uses_its_clock() {
+        const KI_ID: u32 = 1;
+        let mut grid = Grid::with_dimensions(&[4, 4, 4], 4);
+        let clock = Clock::new(Duration::from_millis(500), Duration::from_millis(0));
+        let ki = KiPlayer::with_time_control(KI_ID, TimeControl::Clock(clock));
+        ki.make_turn(&LineRules::default(), &mut grid);
+
+        match *ki.time_control.borrow() {
+            TimeControl::Clock(clock) => assert!(clock.remaining < Duration::from_millis(500)),
+            _ => panic!("The time control of the Ki changed"),
+        }
+        assert_eq!(1, grid.get_indices_with_state(CellState::Set(PlayerId(KI_ID))).len());
+    }
+
+    #[test]
+    fn ki_follows_game_clock() {
+        const KI_ID: u32 = 2;
+        let ki = KiPlayer::new(KI_ID);
+        let clock = Clock::new(Duration::from_secs(60), Duration::from_secs(1));
+        let clocks = [TimeControl::Unlimited, TimeControl::Clock(clock)];
+        Player::<LineRules>::update_clocks(&ki, &clocks);
+        assert_eq!(TimeControl::Clock(clock), *ki.game_clock.borrow());
+
+        // a tighter limit of its own still counts
+        let mut grid = Grid::new(7, 7, 4);
+        let mut time_control = TimeControl::PerMove(Duration::from_millis(50));
+        let ki = KiPlayer::with_time_control(KI_ID, time_control);
+        Player::<LineRules>::update_clocks(&ki, &clocks);
+        let start = Instant::now();
+        ki.make_turn(&LineRules::default(), &mut grid);
+        assert!(time_control.spend(start.elapsed()), "The Ki took {:?}", start.elapsed());
+        assert_eq!(TimeControl::PerMove(Duration::from_millis(50)), *ki.time_control.borrow());
+    }
+
+    // a search without any table or symmetries, to compare the results with
+    fn plain_minimax<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> GameEvaluation {
+        if let Some(evaluation) = evaluate_game(rules, grid, player) {
+            return evaluation;
+        }
+        let mut best = GameEvaluation::Lose;
+        for mov in rules.legal_moves(grid, player) {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, &mov);
+            match plain_minimax(rules, &new_grid, rules.next_player(player)) {
+                GameEvaluation::Lose => return GameEvaluation::Win,
+                GameEvaluation::Draw => best = GameEvaluation::Draw,
+                GameEvaluation::Win => {},
+            }
+        }
+        best
+    }
+
+    fn search_result<R: Rules>(rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> GameEvaluation {
+        let mut search = Search::new(u32::MAX, None, &LineHeuristic);
+        let position = GridPosition {
+            rules,
+            grid: grid.clone(),
+            candidates: None,
+        };
+        minimax(&position, player, 0, &mut search).unwrap().value.evaluation
+    }
+
+    fn bitboard_result(rules: &LineRules, grid: &Grid, player: PlayerId) -> GameEvaluation {
+        let mut search = Search::new(u32::MAX, None, &LineHeuristic);
+        let position = BitPosition {
+            rules: *rules,
+            board: BitBoard::from_grid(grid, |cell| rules.owner(cell)).unwrap(),
+            candidates: None,
+        };
+        minimax(&position, player, 0, &mut search).unwrap().value.evaluation
+    }
+
+    #[test]
+    fn test_horizon_is_estimated_by_the_evaluator() {
+        let rules = LineRules::default();
+        let position = BitPosition {
+            rules,
+            board: BitBoard::from_grid(&Grid::new(3, 3, 3), |cell| rules.owner(cell)).unwrap(),
+            candidates: None,
+        };
+        // the center lies on the most lines
+        let mut search = Search::new(1, None, &LineHeuristic);
+        let outcome = minimax(&position, PlayerId(1), 0, &mut search).unwrap();
+        assert_eq!(Some(4), outcome.mov);
+        assert!(outcome.value.estimate > 0 && outcome.value.score() > 0);
+
+        // a model rewarding the lines the player to move faces makes player 1 take an edge, on the fewest lines
+        let mut model = LinearModel::default();
+        model.opponent[0] = 0.1;
+        let mut search = Search::new(1, None, &model);
+        let outcome = minimax(&position, PlayerId(1), 0, &mut search).unwrap();
+        assert!([1, 3, 5, 7].contains(&outcome.mov.unwrap()));
+
+        // misère lines aren't judged, the positions count as draws
+        let misere = BitPosition {
+            rules: LineRules { misere: true },
+            ..position
+        };
+        let outcome = minimax(&misere, PlayerId(1), 0, &mut search).unwrap();
+        assert_eq!(0, outcome.value.estimate);
+    }
+
+    #[test]
+    fn test_forced_wins_on_large_boards() {
+        let rules = LineRules::default();
+        let mut grid = Grid::new(15, 15, 5);
+        // a four in the row and one in the column at once, after (7, 7)
+        for &(row, column) in &[(7, 4), (7, 5), (7, 6), (4, 7), (5, 7), (6, 7)] {
+            grid.set_cell(row, column, PlayerId(1));
+        }
+        for &(row, column) in &[(7, 3), (3, 7), (0, 0), (0, 1), (0, 2), (14, 14)] {
+            grid.set_cell(row, column, PlayerId(2));
+        }
+        let time_control = TimeControl::PerMove(Duration::from_millis(50));
+        let (mov, value) = KiPlayer::with_time_control(1, time_control).evaluated_move(&rules, &grid);
+        assert_eq!(grid.index_of(&[7, 7]), mov.index);
+        assert_eq!(Some(Value { evaluation: GameEvaluation::Win, moves: 3, estimate: 0 }), value);
+
+        // as o, taking the crossing or one of its ends leaves no forced win
+        let mov = KiPlayer::with_time_control(2, time_control).best_move(&rules, &grid);
+        assert!([grid.index_of(&[7, 7]), grid.index_of(&[7, 8]), grid.index_of(&[8, 7])].contains(&mov.index));
+        rules.apply_move(&mut grid, PlayerId(2), &mov);
+        let mut threats = Threats::from_grid(&grid, |cell| rules.owner(cell)).unwrap();
+        assert_eq!(None, threats.victory_by_fours(PlayerId(1)));
+    }
+
+    #[test]
+    fn test_moves_stay_near_the_stones() {
+        let rules = LineRules::default();
+        let time_control = TimeControl::PerMove(Duration::from_millis(50));
+        let mut grid = Grid::new(15, 15, 5);
+        let ki = KiPlayer::with_time_control(1, time_control);
+        ki.make_turn(&rules, &mut grid);
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(7, 7));
+        assert!(!ki.exact());
+
+        grid.set_cell(8, 8, PlayerId(2));
+        ki.make_turn(&rules, &mut grid);
+        let stones = grid.get_indices_with_state(CellState::Set(PlayerId(1)));
+        assert_eq!(2, stones.len());
+        for &index in &stones {
+            let coordinates = grid.coordinates(index);
+            assert!(coordinates.iter().all(|&coordinate| (5 ..= 10).contains(&coordinate)), "{:?}", coordinates);
+        }
+
+        // on a bitboard the moves are pruned only when asked for, and a win is still found
+        let mut grid = Grid::new(6, 6, 4);
+        for column in 1 .. 4 {
+            grid.set_cell(2, column, PlayerId(1));
+        }
+        grid.set_cell(2, 0, PlayerId(2));
+        grid.set_cell(3, 3, PlayerId(2));
+        grid.set_cell(4, 3, PlayerId(2));
+        let ki = KiPlayer::with_time_control(1, time_control).with_candidate_distance(1);
+        ki.make_turn(&rules, &mut grid);
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(2, 4));
+    }
+
+    #[test]
+    fn test_estimated_value() {
+        let value = Value::estimate(0.25);
+        assert_eq!(250, value.estimate);
+        assert_eq!(-250, value.for_parent().estimate);
+        assert_eq!(1000, Value::estimate(3.0).estimate);
+        assert!(Value::end(GameEvaluation::Win).score() > value.score());
+        assert!(value.score() > Value::end(GameEvaluation::Draw).score());
+        assert_eq!("unclear, estimated +0.250", value.to_string());
+        assert_eq!("draw", Value::end(GameEvaluation::Draw).to_string());
+    }
+
+    #[test]
+    fn symmetry_reduced_search_gives_same_results() {
+        let mut rectangle = Grid::new(3, 4, 3);
+        rectangle.set_cell(1, 1, PlayerId(1));
+        rectangle.set_cell(0, 1, PlayerId(2));
+        let mut torus = Grid::with_topology(&[3, 3], 3, Topology::Toroidal);
+        torus.set_cell(0, 0, PlayerId(1));
+        let mut grids = vec![Grid::new(3, 3, 3), rectangle, torus];
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grids.push(grid.clone());
+        grid.set_cell(0, 1, PlayerId(2));
+        grids.push(grid.clone());
+        grid.set_cell(1, 1, PlayerId(1));
+        grids.push(grid);
+
+        for grid in &grids {
+            for &misere in &[false, true] {
+                let rules = LineRules { misere };
+                assert_eq!(plain_minimax(&rules, grid, PlayerId(2)), search_result(&rules, grid, PlayerId(2)));
+                assert_eq!(plain_minimax(&rules, grid, PlayerId(2)), bitboard_result(&rules, grid, PlayerId(2)));
+            }
+        }
+        let mut wild_grid = Grid::new(3, 3, 3);
+        wild_grid.set_cell(1, 1, PlayerId(1));
+        let rules = WildRules::default();
+        assert_eq!(plain_minimax(&rules, &wild_grid, PlayerId(2)), search_result(&rules, &wild_grid, PlayerId(2)));
+    }
+
+    #[test]
+    fn symmetric_root_moves_are_searched_once() {
+        let rules = LineRules::default();
+        let position = |grid: Grid| GridPosition { rules: &rules, grid, candidates: None };
+        assert_eq!(3, distinct_moves(&position(Grid::new(3, 3, 3)), PlayerId(1), &None).len());
+        assert_eq!(4, distinct_moves(&position(Grid::new(3, 4, 3)), PlayerId(1), &None).len());
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        // only the diagonal through the corner is left as a mirror axis
+        assert_eq!(5, distinct_moves(&position(grid.clone()), PlayerId(2), &None).len());
+        let board = BitPosition {
+            rules,
+            board: BitBoard::from_grid(&grid, |cell| rules.owner(cell)).unwrap(),
+            candidates: None,
+        };
+        assert_eq!(5, distinct_moves(&board, PlayerId(2), &None).len());
+    }
+
+    #[test]
+    fn ki_takes_fastest_win() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(2, 0, PlayerId(KI_ID));
+        grid.set_cell(2, 1, PlayerId(KI_ID));
+        grid.set_cell(0, 1, PlayerId(OPPONENT_ID));
+        grid.set_cell(1, 2, PlayerId(OPPONENT_ID));
+        // 0 0 would win as well with a fork, but only later
+        KiPlayer::new(KI_ID).make_turn(&LineRules::default(), &mut grid);
+
+        assert_eq!(CellState::Set(PlayerId(KI_ID)), *grid.get_cell(2, 2));
+    }
+
+    // Every position after `plies` moves from `grid`, with the player to move.
+    fn positions(rules: &LineRules, grid: &Grid, player: PlayerId, plies: u32, result: &mut Vec<(Grid, PlayerId)>) {
+        if plies == 0 {
+            result.push((grid.clone(), player));
+        } else if evaluate_game(rules, grid, player).is_none() {
+            for mov in rules.legal_moves(grid, player) {
+                let mut new_grid = grid.clone();
+                rules.apply_move(&mut new_grid, player, &mov);
+                positions(rules, &new_grid, rules.next_player(player), plies - 1, result);
+            }
+        }
+    }
+
+    #[test]
+    fn ki_wins_fastest_and_loses_slowest_like_solved_database() {
+        let rules = LineRules::default();
+        let database = Database::solve(&rules, &Grid::new(3, 3, 3));
+        let mut decided = Vec::new();
+        positions(&rules, &Grid::new(3, 3, 3), PlayerId(1), 3, &mut decided);
+        decided.retain(|&(ref grid, player)| {
+            evaluate_game(&rules, grid, player).is_none()
+                && database.value(&rules, grid, player).unwrap().evaluation != GameEvaluation::Draw
+        });
+        assert!(!decided.is_empty());
+
+        for (grid, player) in decided {
+            let PlayerId(id) = player;
+            let mut new_grid = grid.clone();
+            KiPlayer::new(id).make_turn(&rules, &mut new_grid);
+            let perfect = database.value(&rules, &grid, player).unwrap();
+            let played = database.value(&rules, &new_grid, rules.next_player(player)).unwrap().for_parent();
+            assert_eq!(perfect, played);
+        }
+    }
+
+    // How many moves of `player` lose on `grid` with perfect play.
+    fn blunders(rules: &LineRules, grid: &Grid, player: PlayerId) -> usize {
+        rules.legal_moves(grid, player).iter().filter(|mov| {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, mov);
+            plain_minimax(rules, &new_grid, rules.next_player(player)) == GameEvaluation::Win
+        }).count()
+    }
+
+    #[test]
+    fn ki_draws_with_most_chances_for_opponent_to_err() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let rules = LineRules::default();
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(KI_ID));
+        grid.set_cell(1, 1, PlayerId(OPPONENT_ID));
+        let mut chances = Vec::new();
+        for mov in rules.legal_moves(&grid, PlayerId(KI_ID)) {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, PlayerId(KI_ID), &mov);
+            if plain_minimax(&rules, &new_grid, PlayerId(OPPONENT_ID)) == GameEvaluation::Draw {
+                chances.push(blunders(&rules, &new_grid, PlayerId(OPPONENT_ID)));
+            }
+        }
+        let most = *chances.iter().max().unwrap();
+        assert!(chances.iter().any(|&count| count < most), "Every draw gives the same chances");
+
+        KiPlayer::new(KI_ID).make_turn(&rules, &mut grid);
+        assert_eq!(most, blunders(&rules, &grid, PlayerId(OPPONENT_ID)));
+    }
+
+    #[test]
+    fn analyze_evaluates_every_move() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let rules = LineRules::default();
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(2, 0, PlayerId(KI_ID));
+        grid.set_cell(2, 1, PlayerId(KI_ID));
+        grid.set_cell(0, 1, PlayerId(OPPONENT_ID));
+        grid.set_cell(1, 2, PlayerId(OPPONENT_ID));
+        let analyses = analyze(&rules, &grid, PlayerId(KI_ID), None);
+
+        assert_eq!(5, analyses.len());
+        let best = &analyses[0];
+        assert_eq!(grid.index_of(&[2, 2]), best.mov.index);
+        assert_eq!("win in 1", best.value.to_string());
+        assert_eq!(vec![best.mov], best.variation);
+
+        // the fork wins, but only after the opponent blocked one of the lines
+        let fork = analyses.iter().find(|analysis| analysis.mov.index == grid.index_of(&[0, 0])).unwrap();
+        assert_eq!("win in 3", fork.value.to_string());
+        assert_eq!(3, fork.variation.len());
+        let mut end = grid.clone();
+        let mut player = PlayerId(KI_ID);
+        for mov in &fork.variation {
+            assert!(rules.apply_move(&mut end, player, mov));
+            player = rules.next_player(player);
+        }
+        match rules.game_state(&end) {
+            GameState::Win(PlayerId(KI_ID)) => {},
+            _ => panic!("The principal variation doesn't end in the win"),
+        }
+        assert!(analyses.iter().all(|analysis| analysis.exact));
+    }
+
+    #[test]
+    fn analyze_with_time_limit_estimates_large_boards() {
+        let rules = LineRules::default();
+        let mut grid = Grid::new(15, 15, 5);
+        grid.set_cell(7, 7, PlayerId(1));
+        let start = Instant::now();
+        let analyses = analyze(&rules, &grid, PlayerId(2), Some(Duration::from_millis(200)));
+        assert!(start.elapsed() < Duration::from_secs(2), "The analysis didn't stop in time");
+
+        assert_eq!(224, analyses.len());
+        assert!(analyses.iter().any(|analysis| !analysis.exact));
+        let unclear = analyses.iter().find(|analysis| !analysis.exact).unwrap();
+        assert!(unclear.describe_value().starts_with("unclear, estimated"), "{}", unclear.describe_value());
+
+        // small boards are still solved within the time
+        let grid = Grid::new(3, 3, 3);
+        let analyses = analyze(&rules, &grid, PlayerId(1), Some(ANALYSIS_TIME));
+        assert!(analyses.iter().all(|analysis| analysis.exact));
+        assert_eq!("draw", analyses[0].describe_value());
+    }
+
+    #[test]
+    fn test_value_display() {
+        assert_eq!("draw", Value::end(GameEvaluation::Draw).to_string());
+        assert_eq!("loss in 1", Value::end(GameEvaluation::Win).for_parent().to_string());
+    }
+
+    #[test]
+    fn test_game_evaluation_misere_lose() {
+        const KI_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let rules = LineRules { misere: true };
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(OPPONENT_ID));
+        grid.set_cell(0, 1, PlayerId(OPPONENT_ID));
+        grid.set_cell(0, 2, PlayerId(OPPONENT_ID));
+        assert_eq!(GameEvaluation::Win, evaluate_game(&rules, &grid, PlayerId(KI_ID)).unwrap());
+        assert_eq!(GameEvaluation::Lose, evaluate_game(&rules, &grid, PlayerId(OPPONENT_ID)).unwrap());
+    }
+
     #[test]
     fn test_game_evaluation_undetermined() {
         const KI_ID: u32 = 1;
         let mut grid = Grid::new(3, 3, 3);
-        assert!(evaluate_game(&grid, PlayerId(KI_ID)).is_none());
+        assert!(evaluate_game(&LineRules::default(), &grid, PlayerId(KI_ID)).is_none());
         grid.set_cell(0, 0, PlayerId(KI_ID));
-        assert!(evaluate_game(&grid, PlayerId(KI_ID)).is_none());
+        assert!(evaluate_game(&LineRules::default(), &grid, PlayerId(KI_ID)).is_none());
     }
 
     #[test]
@@ -195,7 +1376,7 @@ mod test {
         grid.set_cell(0, 0, PlayerId(KI_ID));
         grid.set_cell(0, 1, PlayerId(KI_ID));
         grid.set_cell(0, 2, PlayerId(KI_ID));
-        assert_eq!(GameEvaluation::Win, evaluate_game(&grid, PlayerId(KI_ID)).unwrap());
+        assert_eq!(GameEvaluation::Win, evaluate_game(&LineRules::default(), &grid, PlayerId(KI_ID)).unwrap());
     }
 
     #[test]
@@ -206,6 +1387,6 @@ mod test {
         grid.set_cell(0, 0, PlayerId(OPPONENT_ID));
         grid.set_cell(0, 1, PlayerId(OPPONENT_ID));
         grid.set_cell(0, 2, PlayerId(OPPONENT_ID));
-        assert_eq!(GameEvaluation::Lose, evaluate_game(&grid, PlayerId(KI_ID)).unwrap());
+        assert_eq!(GameEvaluation::Lose, evaluate_game(&LineRules::default(), &grid, PlayerId(KI_ID)).unwrap());
     }
 }
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use ::player::Player;
+use ::player::ki::{evaluate_game, GameEvaluation, KiPlayer};
+use ::player::storage::{self, Setup};
+use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::game::clock::TimeControl;
+use ::game::{Cell, PlayerId, PLAYER_COUNT};
+use ::random::Random;
+
+const MAGIC: &[u8] = b"tttlearn";
+
+// how far a value moves towards what was learned from a game
+const LEARNING_RATE: f64 = 0.2;
+// how much less a reward counts for every move of the player it is away
+const DISCOUNT: f64 = 0.9;
+// the share of moves a learner picks at random while training, to find better moves
+const EXPLORATION: f64 = 0.1;
+
+// the player who moved and the codes of the cells of the canonical grid after the move
+type Afterstate = (u32, Box<[u32]>);
+
+/// What a learning player knows: for every position after one of its moves, what that move is
+/// worth, from -1 for a loss to 1 for a win. It starts empty, counting every move as 0, and
+/// learns by Q-learning on these afterstates while playing. Positions are stored in their
+/// canonical form, so one value covers all rotations and reflections.
+///
+/// On disk a table is the magic bytes `tttlearn`, the rules and board it was made for and then
+/// one entry per position: the player who moved, one byte per cell and the value as a 64 bit float.
+pub struct QTable {
+    setup: Setup,
+    values: HashMap<Afterstate, f64>,
+}
+
+/// How the games of a stretch of training ended for the learner, for player 1 when it plays itself.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Report {
+    // the games played so far, including this stretch
+    pub episodes: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// Whom a learner plays while training.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+pub enum Opponent {
+    // both players learn from the same table
+    Itself,
+    // the minimax player, the learner taking turns with player 1 and player 2
+    Minimax(TimeControl),
+}
+
+impl QTable {
+    /// An empty table for the rules and board of `grid`.
+    pub fn new<R: Rules>(rules: &R, grid: &Grid<R::Cell>) -> QTable {
+        QTable {
+            setup: Setup::new(rules, grid),
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Whether the table was made for these rules and this board.
+    pub fn fits<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) -> bool {
+        self.setup.fits(rules, grid)
+    }
+
+    /// What the table knows about the move of `player` that led to `grid`, 0 if nothing.
+    pub fn value<C: Cell>(&self, grid: &Grid<C>, player: PlayerId) -> f64 {
+        self.values.get(&afterstate(grid, player)).cloned().unwrap_or(0.0)
+    }
+
+    // The legal moves of `player` with the value and afterstate of each.
+    fn evaluate_moves<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId)
+        -> Vec<(R::Move, f64, Afterstate)> {
+        rules.legal_moves(grid, player).into_iter().map(|mov| {
+            let mut after = grid.clone();
+            rules.apply_move(&mut after, player, &mov);
+            let state = afterstate(&after, player);
+            let value = self.values.get(&state).cloned().unwrap_or(0.0);
+            (mov, value, state)
+        }).collect()
+    }
+
+    /// The move of `player` the table values most, the first of the best ones.
+    pub fn best_move<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> R::Move {
+        let mut best: Option<(R::Move, f64)> = None;
+        for (mov, value, _) in self.evaluate_moves(rules, grid, player) {
+            if best.as_ref().is_none_or(|&(_, best_value)| value > best_value) {
+                best = Some((mov, value));
+            }
+        }
+        best.expect("No possible move, even though the game shouldn't be finished.").0
+    }
+
+    fn learn(&mut self, state: Afterstate, target: f64) {
+        let value = self.values.entry(state).or_insert(0.0);
+        *value += LEARNING_RATE * (target - *value);
+    }
+
+    pub fn load(path: &str) -> io::Result<QTable> {
+        QTable::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read_from<T: Read>(reader: &mut T) -> io::Result<QTable> {
+        let setup = Setup::read_from(reader, MAGIC)?;
+        let cell_count = setup.cell_count();
+        let mut values = HashMap::new();
+        for _ in 0 .. storage::read_u32(reader)? {
+            let player = u32::from(storage::read_u8(reader)?);
+            let cells = storage::read_codes(reader, cell_count)?;
+            values.insert((player, cells), storage::read_f64(reader)?);
+        }
+        Ok(QTable {
+            setup,
+            values,
+        })
+    }
+
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.setup.write_to(writer, MAGIC)?;
+        writer.write_all(&(self.values.len() as u32).to_le_bytes())?;
+        // sorted, so the same table always gives the same file
+        let mut values: Vec<_> = self.values.iter().collect();
+        values.sort_by(|first, second| first.0.cmp(second.0));
+        for (&(player, ref cells), value) in values {
+            writer.write_all(&[player as u8])?;
+            storage::write_codes(writer, cells)?;
+            writer.write_all(&value.to_bits().to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn afterstate<C: Cell>(grid: &Grid<C>, player: PlayerId) -> Afterstate {
+    let PlayerId(id) = player;
+    (id, storage::codes(&grid.canonical()))
+}
+
+/// Plays `episodes` games from `start`, learning from every one of them, and reports how the
+/// games ended for every `block` games.
+pub fn train<R: Rules>(rules: &R, start: &Grid<R::Cell>, table: &mut QTable, opponent: Opponent,
+                       episodes: u32, block: u32, random: &mut Random) -> Vec<Report> {
+    let opponents: Vec<_> = (1 ..= PLAYER_COUNT).map(|id| match opponent {
+        Opponent::Minimax(time_control) => Some(KiPlayer::with_time_control(id, time_control)),
+        Opponent::Itself => None,
+    }).collect();
+    let mut reports = Vec::new();
+    let mut report = Report::default();
+    for episode in 0 .. episodes {
+        let learner = match opponent {
+            Opponent::Itself => PlayerId(1),
+            Opponent::Minimax(_) => PlayerId(episode % PLAYER_COUNT + 1),
+        };
+        let opponent_of = |player: PlayerId| match opponent {
+            Opponent::Minimax(_) if player != learner => opponents[player.0 as usize - 1].as_ref(),
+            _ => None,
+        };
+        match play_episode(rules, start, table, &opponent_of, learner, random) {
+            GameEvaluation::Win => report.wins += 1,
+            GameEvaluation::Draw => report.draws += 1,
+            GameEvaluation::Lose => report.losses += 1,
+        }
+        if (episode + 1) % block == 0 || episode + 1 == episodes {
+            report.episodes = episode + 1;
+            reports.push(report);
+            report = Report::default();
+        }
+    }
+    reports
+}
+
+// Plays one game, the players without an opponent learning, and returns how it ended for `learner`.
+fn play_episode<'a, R: Rules, F: Fn(PlayerId) -> Option<&'a KiPlayer>>(rules: &R, start: &Grid<R::Cell>,
+                                                                        table: &mut QTable, opponent_of: &F,
+                                                                        learner: PlayerId, random: &mut Random)
+    -> GameEvaluation {
+    let mut grid = start.clone();
+    let mut player = PlayerId(1);
+    // the position after the latest move of each learning player
+    let mut previous: Vec<Option<Afterstate>> = vec![None; PLAYER_COUNT as usize];
+    while evaluate_game(rules, &grid, player).is_none() {
+        let PlayerId(id) = player;
+        match opponent_of(player) {
+            Some(opponent) => {
+                let mov = opponent.best_move(rules, &grid);
+                rules.apply_move(&mut grid, player, &mov);
+            },
+            None => {
+                let mut moves = table.evaluate_moves(rules, &grid, player);
+                let best = moves.iter().map(|&(_, value, _)| value).fold(f64::NEG_INFINITY, f64::max);
+                // the previous move leads here, so it is worth the best move from here
+                if let Some(state) = previous[id as usize - 1].take() {
+                    table.learn(state, DISCOUNT * best);
+                }
+                let index = if random.next_f64() < EXPLORATION {
+                    random.below(moves.len())
+                } else {
+                    // ties are broken at random, so a new table tries different moves
+                    let best_moves: Vec<_> = (0 .. moves.len()).filter(|&index| moves[index].1 == best).collect();
+                    best_moves[random.below(best_moves.len())]
+                };
+                let (mov, _, state) = moves.swap_remove(index);
+                rules.apply_move(&mut grid, player, &mov);
+                previous[id as usize - 1] = Some(state);
+            },
+        }
+        player = rules.next_player(player);
+    }
+    for (index, state) in previous.into_iter().enumerate() {
+        if let Some(state) = state {
+            let reward = match evaluate_game(rules, &grid, PlayerId(index as u32 + 1)) {
+                Some(GameEvaluation::Win) => 1.0,
+                Some(GameEvaluation::Lose) => -1.0,
+                _ => 0.0,
+            };
+            table.learn(state, reward);
+        }
+    }
+    evaluate_game(rules, &grid, learner).expect("The game ended.")
+}
+
+/// A player making the move its table values most, without exploring or learning.
+pub struct LearningPlayer {
+    id: u32,
+    table: QTable,
+}
+
+impl LearningPlayer {
+    pub fn new(id: u32, table: QTable) -> LearningPlayer {
+        LearningPlayer {
+            id,
+            table,
+        }
+    }
+}
+
+impl<R: Rules> Player<R> for LearningPlayer {
+    fn make_turn (&self, rules: &R, grid: &mut Grid<R::Cell>) {
+        let player = PlayerId(self.id);
+        let mov = self.table.best_move(rules, grid, player);
+        rules.apply_move(grid, player, &mov);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::LineRules;
+    use ::game::{CellState, PlayerId};
+
+    fn trained(episodes: u32) -> QTable {
+        let rules = LineRules::default();
+        let mut table = QTable::new(&rules, &Grid::new(3, 3, 3));
+        train(&rules, &Grid::new(3, 3, 3), &mut table, Opponent::Itself, episodes, episodes, &mut Random::new(5));
+        table
+    }
+
+    #[test]
+    fn test_learns_to_win_and_block() {
+        let rules = LineRules::default();
+        let table = trained(20_000);
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(2));
+        grid.set_cell(0, 1, PlayerId(1));
+        grid.set_cell(2, 2, PlayerId(2));
+        // x wins in the top row rather than blocking the diagonal
+        assert_eq!(grid.index_of(&[0, 2]), table.best_move(&rules, &grid, PlayerId(1)).index);
+
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(1, 1, PlayerId(2));
+        grid.set_cell(2, 2, PlayerId(1));
+        grid.set_cell(0, 1, PlayerId(2));
+        // o threatens the middle column
+        LearningPlayer::new(1, table).make_turn(&rules, &mut grid);
+        assert_eq!(CellState::Set(PlayerId(1)), *grid.get_cell(2, 1));
+    }
+
+    #[test]
+    fn test_learns_against_minimax() {
+        let rules = LineRules::default();
+        let mut start = Grid::new(3, 3, 3);
+        start.set_cell(1, 1, PlayerId(1));
+        start.set_cell(0, 0, PlayerId(2));
+        let mut table = QTable::new(&rules, &start);
+        let reports = train(&rules, &start, &mut table, Opponent::Minimax(TimeControl::Unlimited), 1000, 400,
+                            &mut Random::new(9));
+        assert_eq!(vec![400, 800, 1000], reports.iter().map(|report| report.episodes).collect::<Vec<_>>());
+        assert_eq!(200, reports[2].wins + reports[2].draws + reports[2].losses);
+        // perfect play never loses the drawn start
+        assert!(reports.iter().all(|report| report.wins == 0));
+
+        // without exploring, the learner holds the draw on both sides
+        for &learner_id in &[1, 2] {
+            let ki = KiPlayer::new(3 - learner_id);
+            let mut grid = start.clone();
+            let mut player = PlayerId(1);
+            while evaluate_game(&rules, &grid, player).is_none() {
+                if player == PlayerId(learner_id) {
+                    let mov = table.best_move(&rules, &grid, player);
+                    rules.apply_move(&mut grid, player, &mov);
+                } else {
+                    ki.make_turn(&rules, &mut grid);
+                }
+                player = rules.next_player(player);
+            }
+            assert_eq!(Some(GameEvaluation::Draw), evaluate_game(&rules, &grid, player));
+        }
+    }
+
+    #[test]
+    fn test_table_survives_disk_format() {
+        let table = trained(200);
+        let mut bytes = Vec::new();
+        table.write_to(&mut bytes).unwrap();
+        let loaded = QTable::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(table.len(), loaded.len());
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(1, 1, PlayerId(1));
+        assert_eq!(table.value(&grid, PlayerId(1)), loaded.value(&grid, PlayerId(1)));
+        assert!(loaded.fits(&LineRules::default(), &Grid::new(3, 3, 3)));
+        assert!(!loaded.fits(&LineRules { misere: true }, &Grid::new(3, 3, 3)));
+        assert!(QTable::read_from(&mut &bytes[1 ..]).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+use ::player::Player;
+use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::game::clock::{self, Clock, TimeControl};
+use ::game::{PlayerId, GameState, PLAYER_COUNT};
+use ::random::Random;
+
+// the exploration constant of UCT, sqrt(2) is the textbook value for rewards between 0 and 1
+const EXPLORATION: f64 = 1.414;
+
+/// How much thinking the player may do per move.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+    // a share of the time left on the clock
+    Clock(Clock),
+}
+
+// The budget of a single move.
+#[derive(Copy, Clone)]
+enum Limit {
+    Iterations(u32),
+    Time(Duration),
+}
+
+/// How a game is played to the end from a new node of the search tree.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+pub enum Rollout {
+    // uniformly random moves
+    Random,
+    // random moves, but a move that wins immediately is always taken
+    Heuristic,
+}
+
+/// A Monte Carlo Tree Search player using UCT. It needs no evaluation function, so it plays
+/// any variant and any board size, getting stronger the larger its budget is.
+pub struct MctsPlayer<R: Rules> {
+    id: u32,
+    budget: RefCell<Budget>,
+    rollout: Rollout,
+    random: RefCell<Random>,
+    // the tree of the previous move, to be reused on the next move
+    tree: RefCell<Option<Tree<R>>>,
+}
+
+struct Tree<R: Rules> {
+    root: Node<R::Move>,
+    // the grid at the root node
+    grid: Grid<R::Cell>,
+}
+
+struct Node<M> {
+    // the move leading to this node and the player who made it
+    mov: Option<M>,
+    mover: PlayerId,
+    visits: u32,
+    // the sum of the rollout rewards from the perspective of `mover`
+    reward: f64,
+    children: Vec<Node<M>>,
+    untried: Vec<M>,
+}
+
+impl<M: Clone> Node<M> {
+    fn new(mov: Option<M>, mover: PlayerId, untried: Vec<M>) -> Node<M> {
+        Node {
+            mov,
+            mover,
+            visits: 0,
+            reward: 0.0,
+            children: Vec::new(),
+            untried,
+        }
+    }
+
+    fn uct_child(&self) -> usize {
+        let log_visits = f64::from(self.visits).ln();
+        let mut best = 0;
+        let mut best_value = f64::NEG_INFINITY;
+        for (index, child) in self.children.iter().enumerate() {
+            let visits = f64::from(child.visits);
+            let value = child.reward / visits + EXPLORATION * (log_visits / visits).sqrt();
+            if value > best_value {
+                best = index;
+                best_value = value;
+            }
+        }
+        best
+    }
+
+    fn most_visited_child(&self) -> Option<usize> {
+        (0 .. self.children.len()).max_by_key(|&index| self.children[index].visits)
+    }
+}
+
+impl<R: Rules> MctsPlayer<R> {
+    pub fn new(id: u32, budget: Budget) -> MctsPlayer<R> {
+        MctsPlayer {
+            id,
+            budget: RefCell::new(budget),
+            rollout: Rollout::Random,
+            random: RefCell::new(Random::from_time()),
+            tree: RefCell::new(None),
+        }
+    }
+
+    pub fn with_rollout(mut self, rollout: Rollout) -> MctsPlayer<R> {
+        self.rollout = rollout;
+        self
+    }
+
+    /// Makes the player deterministic, which is mostly useful for tests.
+    pub fn with_seed(self, seed: u64) -> MctsPlayer<R> {
+        *self.random.borrow_mut() = Random::new(seed);
+        self
+    }
+
+    /// Finds the node for `grid` in the tree of the previous move, which works if exactly one
+    /// move was made since then. Otherwise a new tree is started.
+    fn take_root(&self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> Node<R::Move> {
+        if let Some(Tree { mut root, grid: root_grid }) = self.tree.borrow_mut().take() {
+            if root_grid == *grid {
+                return root;
+            }
+            let reached = root.children.iter().position(|child| {
+                let mut child_grid = root_grid.clone();
+                rules.apply_move(&mut child_grid, child.mover, child.mov.as_ref().unwrap());
+                child_grid == *grid
+            });
+            if let Some(index) = reached {
+                return root.children.swap_remove(index);
+            }
+        }
+        // the mover of the root doesn't matter, its reward is never looked at
+        Node::new(None, player, rules.legal_moves(grid, player))
+    }
+
+    fn iterate(&self, rules: &R, node: &mut Node<R::Move>, grid: &mut Grid<R::Cell>,
+               player: PlayerId, random: &mut Random) -> GameState {
+        let result = match rules.game_state(grid) {
+            GameState::Mid => {
+                if !node.untried.is_empty() {
+                    let mov = node.untried.swap_remove(random.below(node.untried.len()));
+                    rules.apply_move(grid, player, &mov);
+                    let next_player = rules.next_player(player);
+                    let mut child = Node::new(Some(mov), player, rules.legal_moves(grid, next_player));
+                    let result = self.play_out(rules, grid, next_player, random);
+                    child.visits += 1;
+                    child.reward += reward(&result, player);
+                    node.children.push(child);
+                    result
+                } else {
+                    let best = node.uct_child();
+                    let child = &mut node.children[best];
+                    rules.apply_move(grid, player, child.mov.as_ref().unwrap());
+                    self.iterate(rules, child, grid, rules.next_player(player), random)
+                }
+            },
+            result => result,
+        };
+        node.visits += 1;
+        node.reward += reward(&result, node.mover);
+        result
+    }
+
+    fn play_out(&self, rules: &R, grid: &mut Grid<R::Cell>, mut player: PlayerId,
+                random: &mut Random) -> GameState {
+        loop {
+            match rules.game_state(grid) {
+                GameState::Mid => {},
+                state => return state,
+            }
+            let moves = rules.legal_moves(grid, player);
+            let winning = if self.rollout == Rollout::Heuristic {
+                moves.iter().position(|mov| {
+                    let mut new_grid = grid.clone();
+                    rules.apply_move(&mut new_grid, player, mov);
+                    match rules.game_state(&new_grid) {
+                        GameState::Win(winner) => winner == player,
+                        _ => false,
+                    }
+                })
+            } else {
+                None
+            };
+            let index = winning.unwrap_or_else(|| random.below(moves.len()));
+            rules.apply_move(grid, player, &moves[index]);
+            player = rules.next_player(player);
+        }
+    }
+}
+
+// The reward of a finished game for `player`: 1 for a win, 0.5 for a draw and 0 for a loss.
+fn reward(result: &GameState, player: PlayerId) -> f64 {
+    match *result {
+        GameState::Win(winner) if winner == player => 1.0,
+        GameState::Lose(loser) | GameState::LossOnTime(loser) if loser != player => 1.0,
+        GameState::Draw => 0.5,
+        _ => 0.0,
+    }
+}
+
+impl<R: Rules> Player<R> for MctsPlayer<R> {
+    fn make_turn (&self, rules: &R, grid: &mut Grid<R::Cell>) {
+        let player = PlayerId(self.id);
+        let mut root = self.take_root(rules, grid, player);
+        let mut random = self.random.borrow_mut();
+
+        let start = Instant::now();
+        let moves_left = grid.empty_cells().count() as u32 / PLAYER_COUNT;
+        let limit = match *self.budget.borrow() {
+            Budget::Iterations(limit) => Limit::Iterations(limit),
+            Budget::Time(limit) => Limit::Time(clock::usable(limit)),
+            Budget::Clock(ref clock) => Limit::Time(clock.budget(moves_left)),
+        };
+        let mut iterations = 0;
+        loop {
+            let done = match limit {
+                Limit::Iterations(limit) => iterations >= limit,
+                Limit::Time(limit) => start.elapsed() >= limit,
+            };
+            // always look at every move at least once
+            if done && root.untried.is_empty() {
+                break;
+            }
+            self.iterate(rules, &mut root, &mut grid.clone(), player, &mut random);
+            iterations += 1;
+        }
+
+        let best = root.most_visited_child().expect("No possible move, even though the game shouldn't be finished.");
+        let child = root.children.swap_remove(best);
+        rules.apply_move(grid, player, child.mov.as_ref().unwrap());
+        *self.tree.borrow_mut() = Some(Tree {
+            root: child,
+            grid: grid.clone(),
+        });
+        if let Budget::Clock(ref mut clock) = *self.budget.borrow_mut() {
+            clock.spend(start.elapsed());
+        }
+    }
+
+    fn update_clocks(&self, clocks: &[TimeControl]) {
+        if let TimeControl::Clock(clock) = clocks[self.id as usize - 1] {
+            *self.budget.borrow_mut() = Budget::Clock(clock);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::player::Player;
+    use ::game::{CellState, PlayerId};
+    use ::game::grid::Grid;
+    use ::game::rules::{LineRules, Placement};
+
+    #[test]
+    fn mcts_makes_winning_move() {
+        const MCTS_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(MCTS_ID));
+        grid.set_cell(0, 1, PlayerId(MCTS_ID));
+        grid.set_cell(1, 0, PlayerId(OPPONENT_ID));
+        grid.set_cell(1, 1, PlayerId(OPPONENT_ID));
+        let mcts = MctsPlayer::new(MCTS_ID, Budget::Iterations(2000)).with_seed(1);
+        mcts.make_turn(&LineRules::default(), &mut grid);
+
+        assert_eq!(CellState::Set(PlayerId(MCTS_ID)), *grid.get_cell(0, 2));
+    }
+
+    #[test]
+    fn mcts_finishes_within_move_time() {
+        let limit = Duration::from_millis(50);
+        let mut grid = Grid::new(7, 7, 4);
+        let mcts = MctsPlayer::new(1, Budget::Time(limit)).with_seed(1);
+        let start = Instant::now();
+        mcts.make_turn(&LineRules::default(), &mut grid);
+
+        assert!(TimeControl::PerMove(limit).spend(start.elapsed()), "MCTS took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn mcts_blocks() {
+        const MCTS_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(1, 0, PlayerId(OPPONENT_ID));
+        grid.set_cell(1, 1, PlayerId(OPPONENT_ID));
+        grid.set_cell(0, 0, PlayerId(MCTS_ID));
+        let mcts = MctsPlayer::new(MCTS_ID, Budget::Iterations(5000))
+            .with_rollout(Rollout::Heuristic)
+            .with_seed(2);
+        mcts.make_turn(&LineRules::default(), &mut grid);
+
+        assert_eq!(CellState::Set(PlayerId(MCTS_ID)), *grid.get_cell(1, 2));
+    }
+
+    #[test]
+    fn mcts_reuses_tree_after_opponent_move() {
+        const MCTS_ID: u32 = 1;
+        const OPPONENT_ID: u32 = 2;
+        let rules = LineRules::default();
+        let mut grid = Grid::new(3, 3, 3);
+        let mcts = MctsPlayer::new(MCTS_ID, Budget::Iterations(500)).with_seed(3);
+        mcts.make_turn(&rules, &mut grid);
+        let opponent_index = grid.get_indices_with_state(CellState::Unset)[0];
+        grid.set_cell_by_index(opponent_index, PlayerId(OPPONENT_ID));
+
+        let root = mcts.take_root(&rules, &grid, PlayerId(MCTS_ID));
+        assert!(root.visits > 0, "The tree of the previous move wasn't reused");
+        assert_eq!(Some(Placement::new(opponent_index, CellState::Set(PlayerId(OPPONENT_ID)))), root.mov);
+    }
+}
@@ -1,8 +1,24 @@
 pub mod terminal;
 pub mod ki;
+pub mod mcts;
+pub mod book;
+pub mod solver;
+pub mod review;
+pub mod puzzle;
+pub mod selfplay;
+pub mod evaluation;
+pub mod learning;
+pub mod threats;
+pub mod candidates;
+mod storage;
 
 use ::game::grid::Grid;
+use ::game::rules::{Rules, LineRules};
+use ::game::clock::TimeControl;
 
-pub trait Player {
-    fn make_turn (&self, grid: &mut Grid);
+pub trait Player<R: Rules = LineRules> {
+    fn make_turn (&self, rules: &R, grid: &mut Grid<R::Cell>);
+
+    /// Tells the player the clocks of all players before its turn, indexed by player id - 1.
+    fn update_clocks(&self, _clocks: &[TimeControl]) {}
 }
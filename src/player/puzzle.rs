@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use ::player::ki::{evaluate_game, GameEvaluation};
+use ::player::solver::Database;
+use ::game::grid::Grid;
+use ::game::position;
+use ::game::rules::Rules;
+use ::game::PlayerId;
+use ::random::Random;
+
+/// A position in which the player to move has a forced win in `moves` moves, counting the
+/// moves of both players.
+///
+/// In a file every puzzle is a line like "xx./oo./... x 1": the position as `position::setup`
+/// reads it and the number of moves. Empty lines and lines starting with '#' are skipped.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Puzzle {
+    pub position: String,
+    pub moves: u32,
+}
+
+impl Puzzle {
+    pub fn parse(line: &str) -> Result<Puzzle, String> {
+        let line = line.trim();
+        let split = line.rfind(char::is_whitespace).ok_or_else(|| format!("'{}' has no number of moves", line))?;
+        let (position, moves) = line.split_at(split);
+        let moves = moves.trim().parse().map_err(|_| format!("invalid number of moves in '{}'", line))?;
+        Ok(Puzzle {
+            position: position.trim().to_string(),
+            moves,
+        })
+    }
+
+    /// The line of the puzzle in a file.
+    pub fn describe(&self) -> String {
+        format!("{} {}", self.position, self.moves)
+    }
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.moves == 1 { "" } else { "s" };
+        write!(f, "win in {} move{}", self.moves, plural)
+    }
+}
+
+pub fn load(path: &str) -> io::Result<Vec<Puzzle>> {
+    read(&fs::read_to_string(path)?).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+pub fn save(path: &str, puzzles: &[Puzzle]) -> io::Result<()> {
+    let lines: Vec<_> = puzzles.iter().map(|puzzle| puzzle.describe() + "\n").collect();
+    fs::write(path, lines.concat())
+}
+
+/// Reads the puzzles of a file, see `Puzzle`.
+pub fn read(text: &str) -> Result<Vec<Puzzle>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(Puzzle::parse)
+        .collect()
+}
+
+/// Mines up to `count` puzzles with a win in `moves` moves from games the computer plays
+/// against itself from `start`. The players mostly play perfectly, but sometimes pick a random
+/// move, which gives the other player the chance of a win. Every puzzle is a different position,
+/// also under symmetry. Solves the board first, so this is only feasible for small boards.
+pub fn generate<R: Rules>(rules: &R, start: &Grid<R::Cell>, count: usize, moves: u32, random: &mut Random)
+    -> Vec<Puzzle> {
+    let database = Database::solve(rules, start);
+    let mut seen = HashSet::new();
+    let mut puzzles = Vec::new();
+    // not every board has such positions, so give up eventually
+    for _ in 0 .. count * 100 {
+        if puzzles.len() >= count {
+            break;
+        }
+        let mut grid = start.clone();
+        let mut player = PlayerId(1);
+        while evaluate_game(rules, &grid, player).is_none() {
+            let value = database.value(rules, &grid, player).expect("The database misses a position.");
+            let PlayerId(id) = player;
+            if value.evaluation == GameEvaluation::Win && value.moves == moves
+                && seen.insert((id, grid.canonical())) && puzzles.len() < count {
+                puzzles.push(Puzzle {
+                    position: position::describe(&grid, player),
+                    moves,
+                });
+            }
+            let mov = if random.below(3) == 0 {
+                let mut legal_moves = rules.legal_moves(&grid, player);
+                let index = random.below(legal_moves.len());
+                legal_moves.swap_remove(index)
+            } else {
+                database.best_move(rules, &grid, player).expect("The database misses a position.")
+            };
+            rules.apply_move(&mut grid, player, &mov);
+            player = rules.next_player(player);
+        }
+    }
+    puzzles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::LineRules;
+    use ::game::Topology;
+
+    #[test]
+    fn test_read_puzzles() {
+        let puzzles = read("# two puzzles\nxx./oo./... x 1\n\nx.o/.x./..o x 1\n").unwrap();
+        assert_eq!(2, puzzles.len());
+        assert_eq!(Puzzle { position: "xx./oo./... x".to_string(), moves: 1 }, puzzles[0]);
+        assert_eq!("x.o/.x./..o x 1", puzzles[1].describe());
+        assert_eq!("win in 1 move", puzzles[1].to_string());
+        assert!(read("xx./oo./... x").is_err());
+        assert!(read("xx./oo./...").is_err());
+    }
+
+    #[test]
+    fn test_generated_puzzles_have_the_win() {
+        let rules = LineRules::default();
+        let start = Grid::new(3, 3, 3);
+        let puzzles = generate(&rules, &start, 5, 3, &mut Random::new(11));
+        assert_eq!(5, puzzles.len());
+        let database = Database::solve(&rules, &start);
+        for puzzle in &puzzles {
+            assert_eq!(3, puzzle.moves);
+            let (grid, player) = position::setup(&rules, &puzzle.position, 3, Topology::Bounded).unwrap();
+            let value = database.value(&rules, &grid, player).unwrap();
+            assert_eq!(GameEvaluation::Win, value.evaluation);
+            assert_eq!(3, value.moves);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+use ::player::ki::{self, GameEvaluation, Value};
+use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::game::PlayerId;
+
+/// A move of a finished game, compared with the best move in its position.
+#[derive(Debug)]
+pub struct MoveReview<M> {
+    // counting from 1
+    pub number: usize,
+    pub player: PlayerId,
+    pub mov: M,
+    // what the player could have reached with best play before the move
+    pub best_value: Value,
+    pub best_move: M,
+    // what the player reached with the move, both from the perspective of the player
+    pub value: Value,
+    // whether the values are proven, otherwise they are estimates of a search that ran out of time
+    pub best_exact: bool,
+    pub exact: bool,
+}
+
+impl<M> MoveReview<M> {
+    /// Whether the move changed the theoretical outcome for the worse.
+    pub fn is_mistake(&self) -> bool {
+        rank(self.value.evaluation) < rank(self.best_value.evaluation)
+    }
+
+    /// A line like "Move 4 by Player 2 (1 1) turned a draw into a loss; 0 2 was correct."
+    pub fn describe<R: Rules<Move = M>>(&self, rules: &R, grid: &Grid<R::Cell>) -> String {
+        let PlayerId(id) = self.player;
+        let head = format!("Move {} by Player {} ({})", self.number, id, rules.describe_move(grid, &self.mov));
+        if self.is_mistake() {
+            format!("{} turned {} into {}; {} was correct.", head, outcome(&self.best_value, self.best_exact),
+                    outcome(&self.value, self.exact), rules.describe_move(grid, &self.best_move))
+        } else {
+            format!("{}: {}", head, self.value.describe(self.exact))
+        }
+    }
+}
+
+/// Analyzes every move of a game played from `start`, searching each position for at most
+/// `time_limit` like `ki::analyze`.
+pub fn review<R: Rules>(rules: &R, start: &Grid<R::Cell>, history: &[(PlayerId, R::Move)],
+                        time_limit: Option<Duration>) -> Vec<MoveReview<R::Move>> {
+    let mut grid = start.clone();
+    let mut reviews = Vec::new();
+    for (index, &(player, ref mov)) in history.iter().enumerate() {
+        let analyses = ki::analyze(rules, &grid, player, time_limit);
+        let played = analyses.iter().find(|analysis| analysis.mov == *mov)
+            .expect("The history contains an illegal move.");
+        reviews.push(MoveReview {
+            number: index + 1,
+            player,
+            mov: mov.clone(),
+            best_value: analyses[0].value,
+            best_move: analyses[0].mov.clone(),
+            value: played.value,
+            best_exact: analyses[0].exact,
+            exact: played.exact,
+        });
+        rules.apply_move(&mut grid, player, mov);
+    }
+    reviews
+}
+
+/// The move `player` made to get from `before` to `after`, if there is one.
+pub fn find_move<R: Rules>(rules: &R, before: &Grid<R::Cell>, after: &Grid<R::Cell>, player: PlayerId)
+    -> Option<R::Move> {
+    rules.legal_moves(before, player).into_iter().find(|mov| {
+        let mut grid = before.clone();
+        rules.apply_move(&mut grid, player, mov) && grid == *after
+    })
+}
+
+// The outcome a value stands for, like "a draw".
+fn outcome(value: &Value, exact: bool) -> &'static str {
+    match value.evaluation {
+        GameEvaluation::Win => "a win",
+        GameEvaluation::Lose => "a loss",
+        GameEvaluation::Draw if exact => "a draw",
+        GameEvaluation::Draw => "an unclear position",
+    }
+}
+
+fn rank(evaluation: GameEvaluation) -> u32 {
+    match evaluation {
+        GameEvaluation::Lose => 0,
+        GameEvaluation::Draw => 1,
+        GameEvaluation::Win => 2,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::{LineRules, Placement};
+    use ::game::{CellState, PlayerId};
+
+    fn placement(grid: &Grid, row: usize, column: usize, player: u32) -> (PlayerId, Placement<CellState>) {
+        (PlayerId(player), Placement::new(grid.index_of(&[row, column]), CellState::Set(PlayerId(player))))
+    }
+
+    #[test]
+    fn test_review_finds_the_losing_move() {
+        let rules = LineRules::default();
+        let grid = Grid::new(3, 3, 3);
+        // answering the center with an edge loses
+        let history = vec![
+            placement(&grid, 1, 1, 1),
+            placement(&grid, 0, 1, 2),
+            placement(&grid, 0, 0, 1),
+            placement(&grid, 2, 2, 2),
+            placement(&grid, 2, 0, 1),
+        ];
+        let reviews = review(&rules, &grid, &history, Some(ki::ANALYSIS_TIME));
+
+        assert_eq!(5, reviews.len());
+        let mistakes: Vec<_> = reviews.iter().filter(|review| review.is_mistake()).collect();
+        assert_eq!(1, mistakes.len());
+        assert_eq!(2, mistakes[0].number);
+        let correct = mistakes[0].best_move.index;
+        assert!([0, 2, 6, 8].contains(&correct), "A corner would have drawn");
+        assert_eq!(format!("Move 2 by Player 2 (0 1) turned a draw into a loss; {} was correct.",
+                           rules.describe_move(&grid, &mistakes[0].best_move)),
+                   mistakes[0].describe(&rules, &grid));
+        assert_eq!("Move 5 by Player 1 (2 0): win in 3", reviews[4].describe(&rules, &grid));
+    }
+
+    #[test]
+    fn test_review_of_large_boards_estimates() {
+        let rules = LineRules::default();
+        let grid = Grid::new(15, 15, 5);
+        let history = vec![placement(&grid, 7, 7, 1), placement(&grid, 8, 8, 2)];
+        let reviews = review(&rules, &grid, &history, Some(Duration::from_millis(100)));
+
+        assert_eq!(2, reviews.len());
+        assert!(!reviews[1].exact);
+        assert!(reviews[1].describe(&rules, &grid).starts_with("Move 2 by Player 2 (8 8): unclear, estimated"));
+    }
+
+    #[test]
+    fn test_find_move() {
+        let rules = LineRules::default();
+        let before = Grid::new(3, 3, 3);
+        let mut after = before.clone();
+        after.set_cell(1, 2, PlayerId(1));
+        let (_, expected) = placement(&before, 1, 2, 1);
+        assert_eq!(Some(expected), find_move(&rules, &before, &after, PlayerId(1)));
+        assert_eq!(None, find_move(&rules, &before, &after, PlayerId(2)));
+    }
+}
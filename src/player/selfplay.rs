@@ -0,0 +1,138 @@
+use ::player::ki::{evaluate_game, GameEvaluation, KiPlayer, Value};
+use ::game::clock::TimeControl;
+use ::game::grid::Grid;
+use ::game::position;
+use ::game::rules::Rules;
+use ::game::{PlayerId, PLAYER_COUNT};
+use ::random::Random;
+
+/// The first line of a dataset, naming the columns of `Sample::csv_line`.
+pub const CSV_HEADER: &str = "position,evaluation,moves,estimate,outcome";
+
+/// A position of a game the computer played against itself, with what its search thought of
+/// the position and how the game ended, both for the player to move.
+#[derive(Debug)]
+pub struct Sample {
+    // as `position::describe` writes it, including the player to move
+    pub position: String,
+    pub player: PlayerId,
+    pub value: Value,
+    // whether the value is proven, otherwise the search ran out of time and estimated it
+    pub exact: bool,
+    pub outcome: GameEvaluation,
+}
+
+impl Sample {
+    /// The sample as a line of a CSV file, e.g. "x.o/.x./... o,loss,2,0.000,loss". An evaluation
+    /// the search couldn't prove is "unclear", with its estimate from -1 to 1.
+    pub fn csv_line(&self) -> String {
+        let evaluation = if self.exact { self.value.evaluation.to_string() } else { "unclear".to_string() };
+        format!("{},{},{},{:.3},{}", self.position, evaluation, self.value.moves,
+                f64::from(self.value.estimate) / 1000.0, self.outcome)
+    }
+}
+
+/// Plays a game of the computer against itself from `start`, with `opening` random moves first
+/// so the games differ, and returns a sample of every position the computer searched with how the
+/// game ended for player 1.
+pub fn play_game<R: Rules>(rules: &R, start: &Grid<R::Cell>, time_control: TimeControl, opening: u32,
+                           random: &mut Random) -> (Vec<Sample>, GameEvaluation) {
+    let mut grid = start.clone();
+    let mut player = PlayerId(1);
+    for _ in 0 .. opening {
+        if evaluate_game(rules, &grid, player).is_some() {
+            break;
+        }
+        let mut moves = rules.legal_moves(&grid, player);
+        let index = random.below(moves.len());
+        rules.apply_move(&mut grid, player, &moves.swap_remove(index));
+        player = rules.next_player(player);
+    }
+
+    let players: Vec<_> = (1 ..= PLAYER_COUNT).map(|id| KiPlayer::with_time_control(id, time_control)).collect();
+    let mut searched = Vec::new();
+    while evaluate_game(rules, &grid, player).is_none() {
+        let PlayerId(id) = player;
+        let ki = &players[id as usize - 1];
+        let (mov, value) = ki.evaluated_move(rules, &grid);
+        if let Some(value) = value {
+            searched.push((position::describe(&grid, player), player, value, ki.exact()));
+        }
+        rules.apply_move(&mut grid, player, &mov);
+        player = rules.next_player(player);
+    }
+
+    let samples = searched.into_iter().map(|(position, player, value, exact)| Sample {
+        position,
+        player,
+        value,
+        exact,
+        outcome: evaluate_game(rules, &grid, player).expect("The game ended."),
+    }).collect();
+    (samples, evaluate_game(rules, &grid, PlayerId(1)).expect("The game ended."))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::LineRules;
+    use ::game::Topology;
+
+    #[test]
+    fn test_perfect_play_ends_as_evaluated() {
+        let rules = LineRules::default();
+        let mut random = Random::new(3);
+        for _ in 0 .. 5 {
+            let (samples, result) = play_game(&rules, &Grid::new(3, 3, 3), TimeControl::Unlimited, 2, &mut random);
+            assert!(!samples.is_empty());
+            let first = &samples[0];
+            let for_first = if first.player == PlayerId(1) { result } else { Value::end(result).for_parent().evaluation };
+            assert_eq!(first.outcome, for_first);
+            for sample in &samples {
+                assert!(sample.exact);
+                assert_eq!(sample.value.evaluation, sample.outcome, "{}", sample.csv_line());
+                assert!(position::setup(&rules, &sample.position, 3, Topology::Bounded).is_ok());
+            }
+            // the moves left shrink by one with every move
+            for pair in samples.windows(2) {
+                assert_eq!(pair[0].value.moves, pair[1].value.moves + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_games_decided_in_the_opening() {
+        let mut random = Random::new(3);
+        // with one in a row the first random move wins, before the computer searched anything
+        let (samples, result) = play_game(&LineRules::default(), &Grid::new(3, 3, 1), TimeControl::Unlimited, 2,
+                                          &mut random);
+        assert!(samples.is_empty());
+        assert_eq!(GameEvaluation::Win, result);
+    }
+
+    #[test]
+    fn test_csv_line() {
+        let sample = Sample {
+            position: "x.o/.x./... o".to_string(),
+            player: PlayerId(2),
+            value: Value {
+                evaluation: GameEvaluation::Lose,
+                moves: 2,
+                estimate: 0,
+            },
+            exact: true,
+            outcome: GameEvaluation::Lose,
+        };
+        assert_eq!("x.o/.x./... o,loss,2,0.000,loss", sample.csv_line());
+        assert_eq!(5, CSV_HEADER.split(',').count());
+
+        let sample = Sample {
+            value: Value::estimate(0.25),
+            exact: false,
+            outcome: GameEvaluation::Win,
+            ..sample
+        };
+        assert_eq!("x.o/.x./... o,unclear,0,0.250,win", sample.csv_line());
+    }
+}
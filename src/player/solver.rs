@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use ::player::ki::{evaluate_game, GameEvaluation, Value};
+use ::player::storage::{self, Setup};
+use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::game::PlayerId;
+
+const MAGIC: &[u8] = b"tttsolv1";
+
+/// The perfect-play value of every position reachable from the start of a game, stored by
+/// canonical position. Only feasible for small boards, e.g. 3x3 or 4x4.
+///
+/// On disk it is the magic bytes `tttsolv1`, the rules and board it was made for and then one
+/// entry per position: the player to move, one byte per cell, the evaluation for the player
+/// to move (0 lose, 1 draw, 2 win) and the number of moves left.
+pub struct Database {
+    setup: Setup,
+    // the player to move and the codes of the canonical cells
+    values: HashMap<(u32, Box<[u32]>), Value>,
+}
+
+impl Database {
+    /// Solves every position reachable from `start`, starting with player 1.
+    pub fn solve<R: Rules>(rules: &R, start: &Grid<R::Cell>) -> Database {
+        let mut database = Database {
+            setup: Setup::new(rules, start),
+            values: HashMap::new(),
+        };
+        database.solve_position(rules, start, PlayerId(1));
+        database
+    }
+
+    fn solve_position<R: Rules>(&mut self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> Value {
+        if let Some(evaluation) = evaluate_game(rules, grid, player) {
+            return Value::end(evaluation);
+        }
+        let PlayerId(id) = player;
+        let key = (id, storage::codes(&grid.canonical()));
+        if let Some(&value) = self.values.get(&key) {
+            return value;
+        }
+        let mut best: Option<Value> = None;
+        for mov in rules.legal_moves(grid, player) {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, &mov);
+            let value = self.solve_position(rules, &new_grid, rules.next_player(player)).for_parent();
+            if best.is_none_or(|best| value.score() > best.score()) {
+                best = Some(value);
+            }
+        }
+        let best = best.expect("No possible move, even though the game shouldn't be finished.");
+        self.values.insert(key, best);
+        best
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Whether the database was made for these rules and this board.
+    pub fn fits<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) -> bool {
+        self.setup.fits(rules, grid)
+    }
+
+    /// The value of `grid` with `player` to move, if the position is known.
+    pub fn value<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> Option<Value> {
+        if !self.fits(rules, grid) {
+            return None;
+        }
+        if let Some(evaluation) = evaluate_game(rules, grid, player) {
+            return Some(Value::end(evaluation));
+        }
+        let PlayerId(id) = player;
+        self.values.get(&(id, storage::codes(&grid.canonical()))).cloned()
+    }
+
+    /// The perfect move of `player` on `grid`: the fastest win, otherwise a draw,
+    /// otherwise the slowest loss.
+    pub fn best_move<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>, player: PlayerId) -> Option<R::Move> {
+        let next_player = rules.next_player(player);
+        let mut best: Option<(R::Move, Value)> = None;
+        for mov in rules.legal_moves(grid, player) {
+            let mut new_grid = grid.clone();
+            rules.apply_move(&mut new_grid, player, &mov);
+            let value = self.value(rules, &new_grid, next_player)?.for_parent();
+            if best.as_ref().is_none_or(|&(_, best)| value.score() > best.score()) {
+                best = Some((mov, value));
+            }
+        }
+        best.map(|(mov, _)| mov)
+    }
+
+    pub fn load(path: &str) -> io::Result<Database> {
+        Database::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read_from<T: Read>(reader: &mut T) -> io::Result<Database> {
+        let setup = Setup::read_from(reader, MAGIC)?;
+        let cell_count = setup.cell_count();
+        let mut values = HashMap::new();
+        for _ in 0 .. storage::read_u32(reader)? {
+            let player = u32::from(storage::read_u8(reader)?);
+            let cells = storage::read_codes(reader, cell_count)?;
+            let evaluation = match storage::read_u8(reader)? {
+                0 => GameEvaluation::Lose,
+                1 => GameEvaluation::Draw,
+                2 => GameEvaluation::Win,
+                _ => return Err(storage::invalid_data("invalid evaluation")),
+            };
+            let moves = u32::from(storage::read_u8(reader)?);
+            values.insert((player, cells), Value {
+                evaluation,
+                moves,
+                estimate: 0,
+            });
+        }
+        Ok(Database {
+            setup,
+            values,
+        })
+    }
+
+    pub fn write_to<T: Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.setup.write_to(writer, MAGIC)?;
+        writer.write_all(&(self.values.len() as u32).to_le_bytes())?;
+        // sorted, so the same database always gives the same file
+        let mut values: Vec<_> = self.values.iter().collect();
+        values.sort_by_key(|&(key, _)| key);
+        for (&(player, ref cells), value) in values {
+            writer.write_all(&[player as u8])?;
+            storage::write_codes(writer, cells)?;
+            let evaluation = match value.evaluation {
+                GameEvaluation::Lose => 0,
+                GameEvaluation::Draw => 1,
+                GameEvaluation::Win => 2,
+            };
+            writer.write_all(&[evaluation, storage::byte(value.moves)?])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::LineRules;
+    use ::game::{CellState, PlayerId};
+
+    #[test]
+    fn test_classic_board_is_a_draw() {
+        let rules = LineRules::default();
+        let grid = Grid::new(3, 3, 3);
+        let database = Database::solve(&rules, &grid);
+        let value = database.value(&rules, &grid, PlayerId(1)).unwrap();
+        assert_eq!(GameEvaluation::Draw, value.evaluation);
+        assert_eq!(9, value.moves);
+    }
+
+    #[test]
+    fn test_fastest_win_is_found() {
+        let rules = LineRules::default();
+        // three in a row on a 3x4 board is won by the first player
+        let grid = Grid::new(3, 4, 3);
+        let database = Database::solve(&rules, &grid);
+        let value = database.value(&rules, &grid, PlayerId(1)).unwrap();
+        assert_eq!(GameEvaluation::Win, value.evaluation);
+        assert_eq!(7, value.moves);
+
+        let mut grid = Grid::new(3, 3, 3);
+        grid.set_cell(0, 0, PlayerId(1));
+        grid.set_cell(0, 1, PlayerId(1));
+        grid.set_cell(1, 0, PlayerId(2));
+        grid.set_cell(1, 1, PlayerId(2));
+        let database = Database::solve(&rules, &Grid::new(3, 3, 3));
+        let mov = database.best_move(&rules, &grid, PlayerId(1)).unwrap();
+        assert_eq!(grid.index_of(&[0, 2]), mov.index);
+        assert_eq!(CellState::Set(PlayerId(1)), mov.cell);
+    }
+
+    #[test]
+    fn test_database_survives_disk_format() {
+        let rules = LineRules { misere: true };
+        let grid = Grid::new(3, 3, 3);
+        let database = Database::solve(&rules, &grid);
+        let mut bytes = Vec::new();
+        database.write_to(&mut bytes).unwrap();
+        let loaded = Database::read_from(&mut &bytes[..]).unwrap();
+
+        assert_eq!(database.len(), loaded.len());
+        assert_eq!(database.value(&rules, &grid, PlayerId(1)), loaded.value(&rules, &grid, PlayerId(1)));
+        assert!(loaded.value(&LineRules::default(), &grid, PlayerId(1)).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::{self, Read, Write};
+use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::game::{Cell, Topology};
+
+/// The rules and board a stored book or database was made for.
+/// On disk it follows the magic bytes of the file.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Setup {
+    variant: String,
+    dimensions: Vec<usize>,
+    to_win: u32,
+    topology: Topology,
+}
+
+impl Setup {
+    pub fn new<R: Rules>(rules: &R, grid: &Grid<R::Cell>) -> Setup {
+        Setup {
+            variant: rules.name().to_string(),
+            dimensions: grid.dimensions().to_vec(),
+            to_win: grid.to_win,
+            topology: grid.topology(),
+        }
+    }
+
+    pub fn fits<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) -> bool {
+        self.variant == rules.name() && self.dimensions == grid.dimensions()
+            && self.to_win == grid.to_win && self.topology == grid.topology()
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.dimensions.iter().product()
+    }
+
+    pub fn read_from<T: Read>(reader: &mut T, magic: &[u8]) -> io::Result<Setup> {
+        let mut file_magic = vec![0; magic.len()];
+        reader.read_exact(&mut file_magic)?;
+        if file_magic != magic {
+            return Err(invalid_data("the file has the wrong format"));
+        }
+        let mut variant = vec![0; read_u8(reader)? as usize];
+        reader.read_exact(&mut variant)?;
+        let variant = String::from_utf8(variant).map_err(|_| invalid_data("invalid variant name"))?;
+        let mut dimensions = Vec::new();
+        for _ in 0 .. read_u8(reader)? {
+            dimensions.push(read_u32(reader)? as usize);
+        }
+        let to_win = read_u32(reader)?;
+        let topology = match read_u8(reader)? {
+            0 => Topology::Bounded,
+            1 => Topology::Toroidal,
+            _ => return Err(invalid_data("invalid topology")),
+        };
+        Ok(Setup {
+            variant,
+            dimensions,
+            to_win,
+            topology,
+        })
+    }
+
+    pub fn write_to<T: Write>(&self, writer: &mut T, magic: &[u8]) -> io::Result<()> {
+        writer.write_all(magic)?;
+        writer.write_all(&[self.variant.len() as u8])?;
+        writer.write_all(self.variant.as_bytes())?;
+        writer.write_all(&[self.dimensions.len() as u8])?;
+        for &size in &self.dimensions {
+            writer.write_all(&(size as u32).to_le_bytes())?;
+        }
+        writer.write_all(&self.to_win.to_le_bytes())?;
+        writer.write_all(&[if self.topology == Topology::Toroidal { 1 } else { 0 }])
+    }
+}
+
+/// The codes of all cells of `grid`.
+pub fn codes<C: Cell>(grid: &Grid<C>) -> Box<[u32]> {
+    (0 .. grid.cell_count()).map(|index| grid.get_cell_by_index(index).code()).collect()
+}
+
+/// Reads the codes of `cell_count` cells, one byte each.
+pub fn read_codes<T: Read>(reader: &mut T, cell_count: usize) -> io::Result<Box<[u32]>> {
+    let mut bytes = vec![0; cell_count];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes.into_iter().map(u32::from).collect())
+}
+
+pub fn write_codes<T: Write>(writer: &mut T, codes: &[u32]) -> io::Result<()> {
+    let bytes = codes.iter().map(|&code| byte(code)).collect::<io::Result<Vec<u8>>>()?;
+    writer.write_all(&bytes)
+}
+
+pub fn byte(code: u32) -> io::Result<u8> {
+    if code > u32::from(u8::MAX) {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "cells above 255 can't be stored"))
+    } else {
+        Ok(code as u8)
+    }
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn read_u8<T: Read>(reader: &mut T) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+pub fn read_u32<T: Read>(reader: &mut T) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+pub fn read_f64<T: Read>(reader: &mut T) -> io::Result<f64> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(f64::from_bits(u64::from_le_bytes(buffer)))
+}
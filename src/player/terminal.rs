@@ -1,10 +1,15 @@
+use std::cell::RefCell;
 use std::io;
 use super::Player;
+use ::game::clock::TimeControl;
 use ::game::PlayerId;
 use ::game::grid::Grid;
+use ::game::rules::Rules;
+use ::player::ki;
 
 pub struct TerminalPlayer {
     id: u32,
+    clocks: RefCell<Vec<TimeControl>>,
 }
 
 impl TerminalPlayer {
@@ -14,43 +19,72 @@ impl TerminalPlayer {
         println!("If you are asked for input, you should enter it in the form 'row column'");
         println!("Row and column numeration starts at 0.");
         println!("Example: To set the cell at row 0 and column 2, enter '0 2'");
+        println!("On boards with more dimensions, enter the layers first, e.g. 'layer row column'.");
+        println!("In wild and numerical games, add the symbol or number to place, e.g. '0 2 1'.");
+        println!("Enter 'hint' to see how every possible turn would end with the best play.");
 
         TerminalPlayer {
-            id: id,
+            id,
+            clocks: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn print_clocks(&self) {
+        let clocks = self.clocks.borrow();
+        if clocks.iter().all(|clock| *clock == TimeControl::Unlimited) {
+            return;
+        }
+        let times: Vec<_> = clocks.iter().enumerate()
+            .map(|(index, clock)| format!("Player {}: {}", index + 1, clock))
+            .collect();
+        println!("Time left: {}", times.join(", "));
+    }
+
+    fn print_hints<R: Rules>(&self, rules: &R, grid: &Grid<R::Cell>) {
+        println!("Possible turns, the best first:");
+        for analysis in ki::analyze(rules, grid, PlayerId(self.id), Some(ki::ANALYSIS_TIME)) {
+            let variation: Vec<_> = analysis.variation.iter()
+                .map(|mov| rules.describe_move(grid, mov))
+                .collect();
+            println!("  {}: {}, e.g. {}", rules.describe_move(grid, &analysis.mov),
+                     analysis.describe_value(), variation.join(", "));
         }
     }
 }
 
-impl Player for TerminalPlayer {
-    fn make_turn (&self, grid: &mut Grid) {
+impl<R: Rules> Player<R> for TerminalPlayer {
+    fn make_turn (&self, rules: &R, grid: &mut Grid<R::Cell>) {
+        let moves = rules.legal_moves(grid, PlayerId(self.id));
         loop {
             println!("\nCurrent state:");
             grid.pretty_print();
+            self.print_clocks();
             println!("Player {}, what is your turn?", self.id);
             let mut input = String::new();
-            io::stdin()
+            let read = io::stdin()
                 .read_line(&mut input)
-                .ok()
                 .expect("failed to read line");
+            if read == 0 {
+                panic!("the input ended before the game did");
+            }
 
-            let split: Vec<_> = input.split_whitespace().collect();
-            assert_eq!(2, split.len());
-
-            let row: usize = split[0]
-                .parse()
-                .ok()
-                .expect("failed to parse the input");
-
-            let column: usize = split[1]
-                .parse()
-                .ok()
-                .expect("failed to parse the input");
-
-            if !grid.set_cell(row, column, PlayerId(self.id)) {
-                println!("Cell {} {} is already set! Try again!",row,column);
-            } else {
-                break;
+            let input = input.split_whitespace().collect::<Vec<_>>().join(" ");
+            if input == "hint" {
+                self.print_hints(rules, grid);
+                continue;
+            }
+            match moves.iter().find(|mov| rules.describe_move(grid, mov) == input) {
+                Some(mov) => {
+                    rules.apply_move(grid, PlayerId(self.id), mov);
+                    break;
+                },
+                None => println!("'{}' is not a possible turn, e.g. '{}' would be. Try again!",
+                                 input, rules.describe_move(grid, &moves[0])),
             }
         }
     }
+
+    fn update_clocks(&self, clocks: &[TimeControl]) {
+        *self.clocks.borrow_mut() = clocks.to_vec();
+    }
 }
@@ -0,0 +1,276 @@
+use ::game::grid::Grid;
+use ::game::{Cell, PlayerId, PLAYER_COUNT};
+
+// how many positions one search of forcing moves may visit, it gives up after that
+const MAX_NODES: u32 = 20_000;
+// how many positions checking all defences together may visit
+const DEFENCE_NODES: u32 = 5 * MAX_NODES;
+
+/// A search of forcing moves for k-in-a-row on boards too large to search everything: a four is
+/// a line one stone short of winning that the opponent has to block right away. A victory by
+/// continuous fours (VCF) makes a four with every move until the opponent can't block them all,
+/// so only a handful of moves is looked at in every position and long forced wins are found fast.
+pub struct Threats {
+    // the owner of every cell, by index
+    cells: Vec<Option<PlayerId>>,
+    lines: Vec<Box<[usize]>>,
+    // the stones of every player on every line, player `id` at `id - 1`
+    counts: Vec<[u32; PLAYER_COUNT as usize]>,
+    // the lines through every cell
+    cell_lines: Vec<Vec<usize>>,
+    to_win: u32,
+    // the positions visited and how many may be
+    nodes: u32,
+    limit: u32,
+    // whether the latest search had fours left it didn't follow because of its depth
+    cut_off: bool,
+}
+
+impl Threats {
+    /// The threats on `grid`, with `owner` telling whose stone a cell is. Gives none if a set
+    /// cell has no owner or fewer than three in a row win, which leaves nothing to force.
+    pub fn from_grid<C: Cell, F: Fn(&C) -> Option<PlayerId>>(grid: &Grid<C>, owner: F) -> Option<Threats> {
+        if grid.to_win < 3 {
+            return None;
+        }
+        let mut threats = Threats {
+            cells: vec![None; grid.cell_count()],
+            lines: grid.lines().to_vec(),
+            counts: vec![[0; PLAYER_COUNT as usize]; grid.lines().len()],
+            cell_lines: vec![Vec::new(); grid.cell_count()],
+            to_win: grid.to_win,
+            nodes: 0,
+            limit: 0,
+            cut_off: false,
+        };
+        for (line, cells) in grid.lines().iter().enumerate() {
+            for &index in cells.iter() {
+                threats.cell_lines[index].push(line);
+            }
+        }
+        for index in 0 .. grid.cell_count() {
+            let cell = grid.get_cell_by_index(index);
+            if *cell != C::unset() {
+                threats.place(index, owner(cell)?);
+            }
+        }
+        Some(threats)
+    }
+
+    /// The cells where `player` completes a line.
+    pub fn winning_cells(&self, player: PlayerId) -> Vec<usize> {
+        self.line_cells(player, self.to_win - 1)
+    }
+
+    /// The shortest forced win of `player` by fours the search finds: the moves of both players
+    /// by cell index, starting with the first move of `player` and ending with the winning one.
+    pub fn victory_by_fours(&mut self, player: PlayerId) -> Option<Vec<usize>> {
+        self.nodes = 0;
+        self.limit = MAX_NODES;
+        self.forced_win(player)
+    }
+
+    /// The cells of `player` after which the opponent has no victory by fours any more, if the
+    /// opponent has one now. None if there is nothing to defend against, or no defence was found.
+    pub fn defences(&mut self, player: PlayerId) -> Option<Vec<usize>> {
+        let opponent = other(player);
+        let sequence = self.victory_by_fours(opponent)?;
+        // a defence blocks a line the attack runs on or forces the attacker to block first
+        let mut candidates = self.line_cells(player, self.to_win - 2);
+        for &attack in sequence.iter().step_by(2) {
+            for &line in &self.cell_lines[attack] {
+                if self.counts[line][player.0 as usize - 1] == 0 {
+                    candidates.extend(self.lines[line].iter().filter(|&&index| self.cells[index].is_none()));
+                }
+            }
+        }
+        candidates.sort();
+        candidates.dedup();
+        self.limit = self.nodes + DEFENCE_NODES;
+        let mut defences = Vec::new();
+        for index in candidates {
+            self.place(index, player);
+            let safe = self.forced_win(opponent).is_none();
+            self.remove(index, player);
+            // a search that ran out of positions proves nothing
+            if self.nodes >= self.limit {
+                break;
+            }
+            if safe {
+                defences.push(index);
+            }
+        }
+        if defences.is_empty() { None } else { Some(defences) }
+    }
+
+    // `victory_by_fours` within the positions left until `limit`.
+    fn forced_win(&mut self, player: PlayerId) -> Option<Vec<usize>> {
+        // a deeper search is only needed if no shorter win exists
+        for depth in 1 ..= self.cells.len() as u32 {
+            self.cut_off = false;
+            if let Some(mut sequence) = self.vcf(player, depth) {
+                sequence.reverse();
+                return Some(sequence);
+            }
+            if !self.cut_off || self.nodes >= self.limit {
+                break;
+            }
+        }
+        None
+    }
+
+    // A win of `attacker` in at most `depth` fours, from the last move to the first.
+    fn vcf(&mut self, attacker: PlayerId, depth: u32) -> Option<Vec<usize>> {
+        self.nodes += 1;
+        if let Some(&win) = self.winning_cells(attacker).first() {
+            return Some(vec![win]);
+        }
+        let defender = other(attacker);
+        let threats = self.winning_cells(defender);
+        let candidates = match threats.len() {
+            0 => self.line_cells(attacker, self.to_win - 2),
+            // the attacker has to block, which only goes on if the block is a four as well
+            1 => threats,
+            _ => return None,
+        };
+        if depth <= 1 || self.nodes >= self.limit {
+            self.cut_off |= !candidates.is_empty();
+            return None;
+        }
+        for index in candidates {
+            self.place(index, attacker);
+            let replies = self.winning_cells(attacker);
+            let result = match replies.len() {
+                0 => None,
+                1 => {
+                    let reply = replies[0];
+                    self.place(reply, defender);
+                    let result = self.vcf(attacker, depth - 1).map(|mut sequence| {
+                        sequence.push(reply);
+                        sequence
+                    });
+                    self.remove(reply, defender);
+                    result
+                },
+                // two ways to win can't both be blocked, the defender blocks one and loses to the other
+                _ => Some(vec![replies[1], replies[0]]),
+            };
+            self.remove(index, attacker);
+            if let Some(mut sequence) = result {
+                sequence.push(index);
+                return Some(sequence);
+            }
+        }
+        None
+    }
+
+    // The empty cells of the lines holding `stones` stones of `player` and none of anyone else.
+    fn line_cells(&self, player: PlayerId, stones: u32) -> Vec<usize> {
+        let PlayerId(id) = player;
+        let mut cells = Vec::new();
+        for (line, counts) in self.counts.iter().enumerate() {
+            let total: u32 = counts.iter().sum();
+            if counts[id as usize - 1] == stones && total == stones {
+                cells.extend(self.lines[line].iter().filter(|&&index| self.cells[index].is_none()));
+            }
+        }
+        cells.sort();
+        cells.dedup();
+        cells
+    }
+
+    fn place(&mut self, index: usize, player: PlayerId) {
+        self.cells[index] = Some(player);
+        for &line in &self.cell_lines[index] {
+            self.counts[line][player.0 as usize - 1] += 1;
+        }
+    }
+
+    fn remove(&mut self, index: usize, player: PlayerId) {
+        self.cells[index] = None;
+        for &line in &self.cell_lines[index] {
+            self.counts[line][player.0 as usize - 1] -= 1;
+        }
+    }
+}
+
+fn other(player: PlayerId) -> PlayerId {
+    let PlayerId(id) = player;
+    PlayerId(id % PLAYER_COUNT + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::game::grid::Grid;
+    use ::game::rules::{Rules, LineRules};
+
+    fn threats(grid: &Grid) -> Threats {
+        Threats::from_grid(grid, |cell| LineRules::default().owner(cell)).unwrap()
+    }
+
+    fn set(grid: &mut Grid, cells: &[(usize, usize)], player: u32) {
+        for &(row, column) in cells {
+            grid.set_cell(row, column, PlayerId(player));
+        }
+    }
+
+    #[test]
+    fn test_finds_a_long_victory_by_fours() {
+        let mut grid = Grid::new(15, 15, 5);
+        // x can make a four in the row and then in the column, sharing (7, 7)
+        set(&mut grid, &[(7, 4), (7, 5), (7, 6), (4, 7), (5, 7), (6, 7)], 1);
+        set(&mut grid, &[(7, 3), (3, 7), (0, 0), (0, 1), (0, 2), (14, 14)], 2);
+        let mut threats = threats(&grid);
+        assert!(threats.winning_cells(PlayerId(1)).is_empty());
+        let sequence = threats.victory_by_fours(PlayerId(1)).expect("x has a double four");
+        assert_eq!(grid.index_of(&[7, 7]), sequence[0]);
+        assert_eq!(3, sequence.len());
+        assert_eq!(None, threats.victory_by_fours(PlayerId(2)));
+    }
+
+    #[test]
+    fn test_follows_fours_to_the_win() {
+        let mut grid = Grid::new(15, 15, 5);
+        // a four in the row forces o to (7, 8), then the diagonal and the column meet at (5, 5)
+        set(&mut grid, &[(7, 5), (7, 6), (7, 7), (6, 4), (4, 6), (3, 5), (4, 5)], 1);
+        set(&mut grid, &[(7, 4), (0, 0), (0, 14), (14, 0), (14, 14), (2, 8), (8, 2)], 2);
+        let mut threats = threats(&grid);
+        let sequence = threats.victory_by_fours(PlayerId(1)).expect("x wins by fours");
+        assert!(sequence.len() >= 5, "{:?}", sequence);
+        assert_eq!(1, sequence.len() % 2);
+        // replaying the sequence, every reply blocks the one four and the last move wins
+        let mut grid = grid.clone();
+        for (number, &index) in sequence.iter().enumerate() {
+            let player = PlayerId(number as u32 % 2 + 1);
+            assert!(grid.set_cell_by_index(index, player));
+        }
+        assert!(LineRules::default().game_state(&grid).is_over());
+    }
+
+    #[test]
+    fn test_defends_against_fours() {
+        let mut grid = Grid::new(15, 15, 5);
+        set(&mut grid, &[(7, 4), (7, 5), (7, 6), (4, 7), (5, 7), (6, 7)], 1);
+        set(&mut grid, &[(7, 3), (3, 7), (0, 0), (0, 1), (0, 2), (14, 14)], 2);
+        let mut threats = threats(&grid);
+        let defences = threats.defences(PlayerId(2)).expect("o can take the crossing");
+        assert!(defences.contains(&grid.index_of(&[7, 7])));
+        for &index in &defences {
+            threats.place(index, PlayerId(2));
+            assert_eq!(None, threats.victory_by_fours(PlayerId(1)));
+            threats.remove(index, PlayerId(2));
+        }
+        assert_eq!(None, threats.defences(PlayerId(1)));
+    }
+
+    #[test]
+    fn test_needs_three_in_a_row() {
+        assert!(Threats::from_grid(&Grid::new(3, 3, 2), |cell| LineRules::default().owner(cell)).is_none());
+        let mut grid = Grid::new(3, 3, 3);
+        set(&mut grid, &[(0, 0), (1, 1)], 1);
+        set(&mut grid, &[(2, 2)], 2);
+        // with three to win two in a line already threaten, and x can make two such lines at once
+        assert!(threats(&grid).victory_by_fours(PlayerId(1)).is_some());
+    }
+}
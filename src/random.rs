@@ -0,0 +1,65 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small xorshift pseudo random number generator, good enough for games but nothing else.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // scramble the seed so that similar seeds don't give similar sequences
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        Random {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    pub fn from_time() -> Random {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("the clock is before 1970");
+        Random::new(now.as_secs() ^ u64::from(now.subsec_nanos()) << 32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number from 0 up to, but excluding, `bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// A number from 0 up to, but excluding, 1.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let mut first = Random::new(7);
+        let mut second = Random::new(7);
+        for _ in 0 .. 10 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_numbers_in_range() {
+        let mut random = Random::new(3);
+        for _ in 0 .. 1000 {
+            assert!(random.below(6) < 6);
+            let number = random.next_f64();
+            assert!((0.0 .. 1.0).contains(&number));
+        }
+    }
+}